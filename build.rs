@@ -1,6 +1,13 @@
-use d3dx12::build::dxc_compile;
-
 fn main() {
+    // The shaders are only used by the viewer, which only builds on Windows.
+    #[cfg(windows)]
+    compile_shaders();
+}
+
+#[cfg(windows)]
+fn compile_shaders() {
+    use d3dx12::build::dxc_compile;
+
     dxc_compile(
         "src/renderer/points_renderer.hlsl",
         "points_renderer.vs.dxil",
@@ -1,8 +1,8 @@
-use vek::{num_traits::Zero, Mat4, Vec2};
+use vek::{num_traits::Zero, Mat4, Vec2, Vec3};
 use windows::Win32::Graphics::Direct3D12::D3D12_VIEWPORT;
 use winit::event::ElementState;
 
-use crate::Mouse;
+use crate::viewer::Mouse;
 
 type Point = Vec2<f32>;
 
@@ -85,6 +85,19 @@ impl Camera {
         self.matrix
     }
 
+    /// The matrix for drawing the view into an image of `size` pixels rather
+    /// than into the viewport. The image shows the same width of the world as
+    /// the viewport, and as much of its height as the image's aspect ratio
+    /// allows, so that the view isn't stretched when the two differ.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn get_matrix_for_size(&self, size: [u32; 2]) -> Mat4<f32> {
+        let viewport_aspect = self.viewport.Width / self.viewport.Height;
+        let image_aspect = size[0] as f32 / size[1] as f32;
+        let fit: Mat4<f32> = Mat4::scaling_3d(Vec3::new(1.0, image_aspect / viewport_aspect, 1.0));
+
+        fit * self.matrix
+    }
+
     fn calculate_matrix(pos: Vec2<f32>, scale: f32) -> Mat4<f32> {
         let translate: Mat4<f32> = Mat4::translation_2d(pos);
         let scale = Mat4::scaling_3d(scale);
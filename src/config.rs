@@ -0,0 +1,247 @@
+use std::path::PathBuf;
+#[cfg(windows)]
+use std::{fs, path::Path};
+
+use anyhow::Result;
+#[cfg(windows)]
+use anyhow::{ensure, Context};
+use clap::Args;
+#[cfg(windows)]
+use rand::Rng;
+#[cfg(windows)]
+use serde::Deserialize;
+use vek::Vec2;
+
+use crate::headless::parse_world_size;
+#[cfg(windows)]
+use crate::{
+    particle_life::{presets::Preset, snapshot::Import, ParticleKind},
+    screenshot,
+};
+
+#[cfg(windows)]
+const DEFAULT_WINDOW_SIZE: [u32; 2] = [1024, 768];
+#[cfg(windows)]
+const DEFAULT_PARTICLES: usize = 50000;
+#[cfg(windows)]
+const MAX_WINDOW_SIZE: u32 = 16384;
+/// The simulation compares every pair of particles, so much more than this
+/// is too slow to watch.
+#[cfg(windows)]
+const MAX_PARTICLES: usize = 1_000_000;
+
+/// Options for the viewer. Any that aren't given on the command line are taken
+/// from the config file, if there is one.
+#[derive(Args)]
+pub struct ViewerArgs {
+    /// TOML file to read options from. Its keys are the same as the long
+    /// option names, with underscores instead of dashes.
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// Size of the window, such as 1024x768.
+    #[arg(long, value_parser = parse_window_size)]
+    window_size: Option<[u32; 2]>,
+
+    #[arg(long)]
+    particles: Option<usize>,
+
+    /// Number of kinds of particle, up to 8.
+    #[arg(long)]
+    species: Option<u32>,
+
+    /// Size of the world, such as 3072x2304. Defaults to three times the size
+    /// of the window in each direction.
+    #[arg(long, value_parser = parse_world_size)]
+    world_size: Option<Vec2<f32>>,
+
+    /// Seed for the initial rules and particle positions.
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Preset file to take the initial rules and settings from, instead of
+    /// generating random rules.
+    #[arg(long)]
+    preset: Option<PathBuf>,
+
+    /// Start with the simulation paused.
+    #[arg(long)]
+    paused: bool,
+
+    /// Screenshot to restore the scene from, instead of taking the particles,
+    /// seed and rules from the other options.
+    #[arg(
+        long,
+        conflicts_with_all = ["particles", "species", "world_size", "seed", "preset"]
+    )]
+    scene: Option<PathBuf>,
+
+    /// CSV or PLY file to take the particles' positions, velocities and kinds
+    /// from, instead of placing them at random. Positions are scaled to fit
+    /// the world.
+    #[arg(long, conflicts_with_all = ["particles", "scene"])]
+    import: Option<PathBuf>,
+}
+
+#[cfg(windows)]
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    window_size: Option<[u32; 2]>,
+    particles: Option<usize>,
+    species: Option<u32>,
+    world_size: Option<[f32; 2]>,
+    seed: Option<u64>,
+    /// Relative to the config file.
+    preset: Option<PathBuf>,
+    paused: Option<bool>,
+}
+
+#[cfg(windows)]
+impl ConfigFile {
+    fn load(path: &Path) -> Result<Self> {
+        let text =
+            fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+        let mut file: ConfigFile =
+            toml::from_str(&text).with_context(|| format!("parsing {}", path.display()))?;
+
+        if let (Some(preset), Some(dir)) = (&file.preset, path.parent()) {
+            file.preset = Some(dir.join(preset));
+        }
+
+        Ok(file)
+    }
+}
+
+/// The viewer's options, checked and with defaults filled in.
+#[cfg(windows)]
+pub struct ViewerConfig {
+    pub window_size: [u32; 2],
+    pub particles: usize,
+    pub species: u32,
+    /// `None` to size the world to the window.
+    pub world_size: Option<Vec2<f32>>,
+    pub seed: u64,
+    pub preset: Option<Preset>,
+    pub paused: bool,
+    /// Particles to start with instead of placing them at random.
+    pub import: Option<Import>,
+}
+
+#[cfg(windows)]
+impl ViewerArgs {
+    /// Combines the command line with the config file and checks the result.
+    pub fn resolve(&self) -> Result<ViewerConfig> {
+        let file = self
+            .config
+            .as_deref()
+            .map(ConfigFile::load)
+            .transpose()?
+            .unwrap_or_default();
+
+        let window_size = self
+            .window_size
+            .or(file.window_size)
+            .unwrap_or(DEFAULT_WINDOW_SIZE);
+        ensure!(
+            window_size
+                .iter()
+                .all(|size| (1..=MAX_WINDOW_SIZE).contains(size)),
+            "window size must be between 1x1 and {MAX_WINDOW_SIZE}x{MAX_WINDOW_SIZE}, got {}x{}",
+            window_size[0],
+            window_size[1]
+        );
+
+        let paused = self.paused || file.paused.unwrap_or(false);
+
+        if let Some(path) = &self.scene {
+            let scene = screenshot::load_scene(path)?;
+            ensure!(
+                scene.particles <= MAX_PARTICLES,
+                "particles must be between 1 and {MAX_PARTICLES}, got {}",
+                scene.particles
+            );
+
+            return Ok(ViewerConfig {
+                window_size,
+                particles: scene.particles,
+                species: scene.species,
+                world_size: Some(scene.world_size),
+                seed: scene.seed,
+                preset: Some(scene.preset),
+                paused,
+                import: None,
+            });
+        }
+
+        let species = self.species.or(file.species).unwrap_or(ParticleKind::MAX);
+        ensure!(
+            (1..=ParticleKind::MAX).contains(&species),
+            "species must be between 1 and {}, got {species}",
+            ParticleKind::MAX
+        );
+
+        let import = self
+            .import
+            .as_deref()
+            .map(|path| Import::load(path, species))
+            .transpose()?;
+
+        let particles = import.as_ref().map_or_else(
+            || {
+                self.particles
+                    .or(file.particles)
+                    .unwrap_or(DEFAULT_PARTICLES)
+            },
+            Import::num_particles,
+        );
+        ensure!(
+            (1..=MAX_PARTICLES).contains(&particles),
+            "particles must be between 1 and {MAX_PARTICLES}, got {particles}"
+        );
+
+        let world_size = self.world_size.or(file.world_size.map(Vec2::from));
+        if let Some(world_size) = world_size {
+            ensure!(
+                world_size
+                    .iter()
+                    .all(|size| size.is_finite() && *size > 0.0),
+                "world size must be greater than zero, got {}x{}",
+                world_size.x,
+                world_size.y
+            );
+        }
+
+        let seed = self
+            .seed
+            .or(file.seed)
+            .unwrap_or_else(|| rand::rng().random());
+
+        let preset = self
+            .preset
+            .as_ref()
+            .or(file.preset.as_ref())
+            .map(|path| {
+                Preset::load(path).with_context(|| format!("loading preset {}", path.display()))
+            })
+            .transpose()?;
+
+        Ok(ViewerConfig {
+            window_size,
+            particles,
+            species,
+            world_size,
+            seed,
+            preset,
+            paused,
+            import,
+        })
+    }
+}
+
+/// Parses a window size given as width by height, such as "1024x768".
+pub fn parse_window_size(text: &str) -> Result<[u32; 2], String> {
+    text.split_once(['x', 'X'])
+        .and_then(|(width, height)| Some([width.trim().parse().ok()?, height.trim().parse().ok()?]))
+        .ok_or_else(|| "expected WIDTHxHEIGHT, such as 1024x768".to_owned())
+}
@@ -0,0 +1,342 @@
+use std::{collections::HashMap, fmt::Write as _, fs, path::PathBuf};
+
+use anyhow::{ensure, Result};
+use clap::Args;
+use rand::{rngs::StdRng, seq::IndexedRandom, Rng, SeedableRng};
+
+use crate::particle_life::{
+    classify::{classify, Behaviour, Classification},
+    cpu::{self, CpuWorld},
+    fitness::{self, DensityGrid},
+    metrics::Metrics,
+    presets::Preset,
+    MutationParameters, RuleGenerationParameters, Rules, ShaderGlobalConstants,
+};
+
+/// Evolve rule sets on the CPU, without opening a window, and write the best
+/// ones out as presets.
+#[derive(Args)]
+pub struct EvolveArgs {
+    /// Number of rule sets in each generation.
+    #[arg(long, default_value_t = 16)]
+    population: usize,
+
+    #[arg(long, default_value_t = 20)]
+    generations: usize,
+
+    /// Number of the best rule sets that are carried unchanged into the next
+    /// generation.
+    #[arg(long, default_value_t = 4)]
+    elite: usize,
+
+    /// Number of particles in each world.
+    #[arg(long, default_value_t = 2000)]
+    particles: usize,
+
+    /// Steps to run before scoring, to let the world settle.
+    #[arg(long, default_value_t = 300)]
+    warmup_steps: usize,
+
+    /// Number of times each world is measured while scoring.
+    #[arg(long, default_value_t = 10)]
+    samples: usize,
+
+    /// Steps between each measurement.
+    #[arg(long, default_value_t = 30)]
+    sample_interval: usize,
+
+    /// Weight given to the number of distinct clusters.
+    #[arg(long, default_value_t = 0.1)]
+    cluster_weight: f32,
+
+    /// Weight given to the mean particle speed.
+    #[arg(long, default_value_t = 0.5)]
+    motion_weight: f32,
+
+    /// Weight given to how much the density of the world stays the same
+    /// between measurements (0 to 1).
+    #[arg(long, default_value_t = 1.0)]
+    persistence_weight: f32,
+
+    #[arg(long, default_value_t = MutationParameters::default().amount)]
+    mutation_amount: f32,
+
+    #[arg(long, default_value_t = MutationParameters::default().randomize_fraction)]
+    mutation_randomize_fraction: f32,
+
+    /// Number of rule sets to write out.
+    #[arg(long, default_value_t = 5)]
+    keep: usize,
+
+    /// Directory to write the rule sets and their scores to.
+    #[arg(long, default_value = "evolved")]
+    output: PathBuf,
+
+    /// Behaviours, such as "frozen" or "gas", that rule sets are rejected
+    /// for. Separate several with commas.
+    #[arg(long, value_delimiter = ',', value_parser = parse_behaviour)]
+    reject: Vec<Behaviour>,
+
+    /// Seed for the initial particle positions. Every rule set in a generation
+    /// starts from the same positions.
+    #[arg(long)]
+    seed: Option<u64>,
+}
+
+#[derive(Clone, Copy)]
+struct Fitness {
+    clusters: f32,
+    motion: f32,
+    persistence: f32,
+    behaviour: Option<Classification>,
+    score: f32,
+}
+
+impl Fitness {
+    fn behaviour_name(&self) -> &'static str {
+        self.behaviour.map_or("unknown", |b| b.behaviour.name())
+    }
+}
+
+fn parse_behaviour(name: &str) -> Result<Behaviour, String> {
+    Behaviour::ALL
+        .into_iter()
+        .find(|b| b.name() == name)
+        .ok_or_else(|| {
+            let names: Vec<_> = Behaviour::ALL.iter().map(|b| b.name()).collect();
+            format!("expected one of {}", names.join(", "))
+        })
+}
+
+struct Individual {
+    id: usize,
+    rules: Rules,
+}
+
+struct Evaluated {
+    rules: Rules,
+    fitness: Fitness,
+    generation: usize,
+}
+
+pub fn run(args: &EvolveArgs) -> Result<()> {
+    ensure!(args.population > 0, "population must be at least 1");
+    ensure!(
+        args.elite < args.population,
+        "elite must be smaller than the population"
+    );
+    ensure!(args.particles > 0, "particles must be at least 1");
+
+    let seed = args.seed.unwrap_or_else(|| rand::rng().random());
+    println!("Seed: {seed}");
+
+    let generation_parameters = RuleGenerationParameters::default();
+    let mutation = MutationParameters {
+        amount: args.mutation_amount,
+        randomize_fraction: args.mutation_randomize_fraction,
+    };
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut next_id = 0;
+    let mut new_individual = |rules| {
+        next_id += 1;
+        Individual { id: next_id, rules }
+    };
+
+    let mut population: Vec<_> = (0..args.population)
+        .map(|_| new_individual(Rules::new_random_with_rng(&generation_parameters, &mut rng)))
+        .collect();
+
+    // Most recent evaluation of every individual, so that elites that survive
+    // several generations are only written out once.
+    let mut evaluated: HashMap<usize, Evaluated> = HashMap::new();
+
+    for generation in 0..args.generations {
+        let world_seed = seed.wrapping_add(generation as u64);
+
+        let mut scored: Vec<(Individual, Fitness)> = population
+            .into_iter()
+            .map(|individual| {
+                let fitness = evaluate(&individual.rules, args, world_seed);
+                (individual, fitness)
+            })
+            .collect();
+        scored.sort_by(|a, b| b.1.score.total_cmp(&a.1.score));
+
+        let best = scored[0].1;
+        #[allow(clippy::cast_precision_loss)]
+        let mean = scored.iter().map(|(_, f)| f.score).sum::<f32>() / scored.len() as f32;
+        println!(
+            "Generation {generation}: best {:.3} (clusters {:.1}, motion {:.3}, persistence {:.3}, {}), mean {mean:.3}",
+            best.score, best.clusters, best.motion, best.persistence, best.behaviour_name()
+        );
+
+        for (individual, fitness) in &scored {
+            evaluated.insert(
+                individual.id,
+                Evaluated {
+                    rules: individual.rules,
+                    fitness: *fitness,
+                    generation,
+                },
+            );
+        }
+
+        // Elites survive unchanged; the rest of the next generation are
+        // mutated children of parents picked by tournament.
+        let mut next: Vec<_> = scored[..args.elite]
+            .iter()
+            .map(|(individual, _)| Individual {
+                id: individual.id,
+                rules: individual.rules,
+            })
+            .collect();
+
+        while next.len() < args.population {
+            let a = tournament(&scored, &mut rng);
+            let b = tournament(&scored, &mut rng);
+            let child =
+                a.crossover(b, &mut rng)
+                    .mutate(&mutation, &generation_parameters, &mut rng);
+            next.push(new_individual(child));
+        }
+
+        population = next;
+    }
+
+    // Scores that aren't finite would sort above every real score, so leave
+    // them out before picking the best.
+    let mut best: Vec<_> = evaluated
+        .into_values()
+        .filter(|result| result.fitness.score.is_finite())
+        .collect();
+    if best.is_empty() {
+        eprintln!("No rules got a finite score, so none were written");
+    }
+    best.sort_by(|a, b| b.fitness.score.total_cmp(&a.fitness.score));
+    best.truncate(args.keep);
+
+    write_results(args, &best)
+}
+
+/// Picks the best of a few randomly chosen individuals.
+fn tournament<'a>(scored: &'a [(Individual, Fitness)], rng: &mut impl Rng) -> &'a Rules {
+    const TOURNAMENT_SIZE: usize = 3;
+
+    let (individual, _) = scored
+        .choose_multiple(rng, TOURNAMENT_SIZE)
+        .max_by(|a, b| a.1.score.total_cmp(&b.1.score))
+        .unwrap();
+    &individual.rules
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn evaluate(rules: &Rules, args: &EvolveArgs, world_seed: u64) -> Fitness {
+    let world_size = cpu::default_world_size(args.particles);
+    let mut world = CpuWorld::new(
+        args.particles,
+        world_size,
+        &mut StdRng::seed_from_u64(world_seed),
+    );
+
+    for _ in 0..args.warmup_steps {
+        world.step(rules);
+    }
+
+    let mut clusters = 0.0;
+    let mut motion = 0.0;
+    let mut persistence = 0.0;
+    let mut previous: Option<DensityGrid> = None;
+    let mut history = Vec::new();
+
+    for _ in 0..args.samples {
+        for _ in 0..args.sample_interval {
+            world.step(rules);
+            history.push(Metrics::measure(world.particles(), world_size));
+        }
+
+        let particles = world.particles();
+        if !fitness::all_finite(particles) {
+            return Fitness {
+                clusters: 0.0,
+                motion: 0.0,
+                persistence: 0.0,
+                behaviour: None,
+                score: f32::NEG_INFINITY,
+            };
+        }
+
+        let grid = DensityGrid::new(particles, world_size);
+        clusters += grid.count_clusters() as f32;
+        motion += fitness::mean_speed(particles);
+        if let Some(previous) = &previous {
+            persistence += previous.similarity(&grid);
+        }
+        previous = Some(grid);
+    }
+
+    let samples = args.samples.max(1) as f32;
+    let clusters = clusters / samples;
+    let motion = motion / samples;
+    let persistence = persistence / (samples - 1.0).max(1.0);
+
+    let behaviour = classify(&history);
+    let rejected = behaviour.is_some_and(|b| args.reject.contains(&b.behaviour));
+
+    Fitness {
+        clusters,
+        motion,
+        persistence,
+        behaviour,
+        score: if rejected {
+            f32::NEG_INFINITY
+        } else {
+            clusters * args.cluster_weight
+                + motion * args.motion_weight
+                + persistence * args.persistence_weight
+        },
+    }
+}
+
+fn write_results(args: &EvolveArgs, best: &[Evaluated]) -> Result<()> {
+    let mut csv = String::from(
+        "rank,file,score,clusters,motion,persistence,behaviour,confidence,generation\n",
+    );
+
+    let settings =
+        ShaderGlobalConstants::new(args.particles, cpu::default_world_size(args.particles));
+
+    for (index, result) in best.iter().enumerate() {
+        let rank = index + 1;
+        let fitness = result.fitness;
+
+        let mut preset = Preset::new(&format!("Evolved {rank}"), &settings, &result.rules);
+        preset.description = format!(
+            "Score {:.3} (clusters {:.1}, motion {:.3}, persistence {:.3}), {}, generation {}",
+            fitness.score,
+            fitness.clusters,
+            fitness.motion,
+            fitness.persistence,
+            fitness.behaviour_name(),
+            result.generation
+        );
+        let path = preset.save(&args.output, true)?;
+
+        writeln!(
+            csv,
+            "{rank},{},{},{},{},{},{},{},{}",
+            path.file_name().unwrap().to_string_lossy(),
+            fitness.score,
+            fitness.clusters,
+            fitness.motion,
+            fitness.persistence,
+            fitness.behaviour_name(),
+            fitness.behaviour.map_or(0.0, |b| b.confidence),
+            result.generation
+        )?;
+        println!("{}: {}", path.display(), preset.description);
+    }
+
+    fs::write(args.output.join("scores.csv"), csv)?;
+    Ok(())
+}
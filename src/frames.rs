@@ -0,0 +1,334 @@
+use std::{
+    fs::{self, File},
+    io::{BufWriter, IntoInnerError, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, ensure, Context, Result};
+use clap::ValueEnum;
+use color_quant::NeuQuant;
+
+use crate::{raster::Image, screenshot};
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum FrameFormat {
+    /// Numbered PNG files in a directory.
+    Png,
+    /// A single uncompressed YUV4MPEG2 stream, which most video tools read.
+    Y4m,
+    /// An animated GIF that loops forever, for short clips.
+    Gif,
+}
+
+#[cfg(windows)]
+impl FrameFormat {
+    pub const ALL: [FrameFormat; 3] = [FrameFormat::Png, FrameFormat::Y4m, FrameFormat::Gif];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            FrameFormat::Png => "PNG",
+            FrameFormat::Y4m => "Y4M",
+            FrameFormat::Gif => "GIF",
+        }
+    }
+}
+
+/// Largest number of colours a GIF frame can have.
+pub const MAX_PALETTE_SIZE: u16 = 256;
+
+/// Samples every this many pixels when choosing a GIF palette. Lower is better
+/// but slower.
+const PALETTE_SAMPLING: i32 = 10;
+
+/// How often, and how, frames are recorded.
+#[derive(Clone)]
+pub struct FrameParameters {
+    pub format: FrameFormat,
+    /// Record a frame every this many steps.
+    pub interval: u64,
+    pub size: [u32; 2],
+    /// Frame rate written into Y4M streams and GIFs. GIFs round the time
+    /// between frames to hundredths of a second.
+    pub fps: u32,
+    /// Number of colours in each GIF frame.
+    pub palette_size: u16,
+    /// Stop after this many steps, or `None` to keep going.
+    pub duration: Option<u64>,
+}
+
+impl Default for FrameParameters {
+    fn default() -> Self {
+        FrameParameters {
+            format: FrameFormat::Png,
+            interval: 1,
+            size: [1024, 768],
+            fps: 30,
+            palette_size: 64,
+            duration: None,
+        }
+    }
+}
+
+/// Writes images of the world every few steps, as numbered PNGs, a Y4M stream
+/// or an animated GIF.
+pub struct FrameRecorder {
+    params: FrameParameters,
+    /// Directory for PNGs, file otherwise.
+    path: PathBuf,
+    output: Output,
+    frames: usize,
+    /// Step that the first frame was taken at.
+    first_step: Option<u64>,
+}
+
+enum Output {
+    Png,
+    Y4m(BufWriter<File>),
+    Gif(gif::Encoder<BufWriter<File>>),
+}
+
+impl FrameRecorder {
+    /// Starts recording to `path`, which is a directory for PNGs and a file
+    /// otherwise.
+    pub fn new(params: FrameParameters, path: &Path) -> Result<Self> {
+        ensure!(params.interval > 0, "frame interval must be at least 1");
+        ensure!(params.fps > 0, "frame rate must be at least 1");
+        ensure!(
+            (2..=MAX_PALETTE_SIZE).contains(&params.palette_size),
+            "palette size must be between 2 and {MAX_PALETTE_SIZE}, got {}",
+            params.palette_size
+        );
+
+        let create = || -> Result<BufWriter<File>> {
+            let file =
+                File::create(path).with_context(|| format!("creating {}", path.display()))?;
+            Ok(BufWriter::new(file))
+        };
+        let [width, height] = params.size;
+
+        let output = match params.format {
+            FrameFormat::Png => {
+                fs::create_dir_all(path).with_context(|| format!("creating {}", path.display()))?;
+                Output::Png
+            }
+            FrameFormat::Y4m => {
+                let mut writer = create()?;
+                writeln!(
+                    writer,
+                    "YUV4MPEG2 W{width} H{height} F{}:1 Ip A1:1 C444",
+                    params.fps
+                )?;
+                Output::Y4m(writer)
+            }
+            FrameFormat::Gif => {
+                let (Ok(width), Ok(height)) = (u16::try_from(width), u16::try_from(height)) else {
+                    bail!("GIFs can be at most 65535x65535, got {width}x{height}");
+                };
+                let mut encoder = gif::Encoder::new(create()?, width, height, &[])?;
+                encoder.set_repeat(gif::Repeat::Infinite)?;
+                Output::Gif(encoder)
+            }
+        };
+
+        Ok(FrameRecorder {
+            params,
+            path: path.to_owned(),
+            output,
+            frames: 0,
+            first_step: None,
+        })
+    }
+
+    pub fn size(&self) -> [u32; 2] {
+        self.params.size
+    }
+
+    pub fn num_frames(&self) -> usize {
+        self.frames
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Whether a frame should be recorded at `step`.
+    pub fn is_due(&self, step: u64) -> bool {
+        step.is_multiple_of(self.params.interval) && !self.is_complete(step)
+    }
+
+    /// Whether the recording has run for its full duration by `step`.
+    pub fn is_complete(&self, step: u64) -> bool {
+        match (self.first_step, self.params.duration) {
+            (Some(first_step), Some(duration)) => step >= first_step + duration,
+            _ => false,
+        }
+    }
+
+    /// Adds the frame for `step`, which must be the size the recorder was
+    /// created with.
+    pub fn write(&mut self, step: u64, image: &Image) -> Result<()> {
+        ensure!(
+            [image.width, image.height] == self.params.size,
+            "frame is {}x{}, expected {}x{}",
+            image.width,
+            image.height,
+            self.params.size[0],
+            self.params.size[1]
+        );
+
+        match &mut self.output {
+            Output::Png => {
+                let path = self.path.join(format!("frame_{:06}.png", self.frames));
+                screenshot::save(&path, image, None)?;
+            }
+            Output::Y4m(writer) => {
+                writer.write_all(b"FRAME\n")?;
+                writer.write_all(&to_ycbcr(image))?;
+            }
+            Output::Gif(encoder) => {
+                let mut frame = to_gif_frame(image, self.params.palette_size);
+                frame.delay = u16::try_from((100 / self.params.fps).max(1)).unwrap();
+                encoder.write_frame(&frame)?;
+            }
+        }
+
+        self.first_step.get_or_insert(step);
+        self.frames += 1;
+        Ok(())
+    }
+
+    /// Flushes any frames that haven't been written yet.
+    pub fn finish(self) -> Result<()> {
+        let writer = match self.output {
+            Output::Png => return Ok(()),
+            Output::Y4m(writer) => writer,
+            Output::Gif(encoder) => encoder.into_inner()?,
+        };
+        writer
+            .into_inner()
+            .map(drop)
+            .map_err(IntoInnerError::into_error)
+            .with_context(|| format!("writing {}", self.path.display()))
+    }
+}
+
+/// Reduces the image to `palette_size` colours, chosen to suit this frame.
+fn to_gif_frame(image: &Image, palette_size: u16) -> gif::Frame<'static> {
+    let quantizer = NeuQuant::new(PALETTE_SAMPLING, usize::from(palette_size), &image.pixels);
+    let indices: Vec<u8> = image
+        .pixels
+        .chunks_exact(4)
+        .map(|pixel| u8::try_from(quantizer.index_of(pixel)).unwrap())
+        .collect();
+
+    gif::Frame::from_palette_pixels(
+        u16::try_from(image.width).unwrap(),
+        u16::try_from(image.height).unwrap(),
+        indices,
+        quantizer.color_map_rgb(),
+        None,
+    )
+}
+
+/// Converts to planar Y, Cb and Cr at full resolution, using the BT.601
+/// studio range that Y4M readers assume.
+fn to_ycbcr(image: &Image) -> Vec<u8> {
+    let num_pixels = image.pixels.len() / 4;
+    let mut planes = vec![0; num_pixels * 3];
+    let (y, rest) = planes.split_at_mut(num_pixels);
+    let (cb, cr) = rest.split_at_mut(num_pixels);
+
+    for (i, pixel) in image.pixels.chunks_exact(4).enumerate() {
+        let [r, g, b] = [pixel[0], pixel[1], pixel[2]].map(|c| f32::from(c) / 255.0);
+
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let to_byte = |v: f32| v.round().clamp(0.0, 255.0) as u8;
+        y[i] = to_byte(16.0 + 65.481 * r + 128.553 * g + 24.966 * b);
+        cb[i] = to_byte(128.0 - 37.797 * r - 74.203 * g + 112.0 * b);
+        cr[i] = to_byte(128.0 + 112.0 * r - 93.786 * g - 18.214 * b);
+    }
+
+    planes
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use super::{FrameFormat, FrameParameters, FrameRecorder};
+    use crate::raster::Image;
+
+    /// A file in the temporary directory that is removed when dropped.
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str) -> Self {
+            TempFile(std::env::temp_dir().join(format!("dplife_{}_{name}", std::process::id())))
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    /// A two by one image with a red pixel and a white one.
+    fn image() -> Image {
+        Image {
+            width: 2,
+            height: 1,
+            pixels: vec![255, 0, 0, 255, 255, 255, 255, 255],
+        }
+    }
+
+    fn record(format: FrameFormat, path: &TempFile, frames: u64) {
+        let params = FrameParameters {
+            format,
+            size: [2, 1],
+            fps: 25,
+            ..FrameParameters::default()
+        };
+        let mut recorder = FrameRecorder::new(params, &path.0).unwrap();
+        for step in 0..frames {
+            recorder.write(step, &image()).unwrap();
+        }
+        assert!(recorder.write(frames, &Image::new(1, 1)).is_err());
+        recorder.finish().unwrap();
+    }
+
+    #[test]
+    fn y4m_has_a_header_and_planar_frames() {
+        let path = TempFile::new("frames.y4m");
+        record(FrameFormat::Y4m, &path, 2);
+
+        // Y, then Cb, then Cr, each plane in pixel order
+        let frame = [b"FRAME\n".as_slice(), &[81, 235, 90, 128, 240, 128]].concat();
+        let expected = [
+            b"YUV4MPEG2 W2 H1 F25:1 Ip A1:1 C444\n".as_slice(),
+            &frame,
+            &frame,
+        ]
+        .concat();
+        assert_eq!(fs::read(&path.0).unwrap(), expected);
+    }
+
+    #[test]
+    fn gif_decodes_to_the_frames_written() {
+        let path = TempFile::new("frames.gif");
+        record(FrameFormat::Gif, &path, 3);
+
+        let file = fs::File::open(&path.0).unwrap();
+        let mut decoder = gif::DecodeOptions::new().read_info(file).unwrap();
+        assert_eq!((decoder.width(), decoder.height()), (2, 1));
+
+        let mut frames = 0;
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            assert_eq!((frame.width, frame.height), (2, 1));
+            // Hundredths of a second at 25 frames a second
+            assert_eq!(frame.delay, 4);
+            frames += 1;
+        }
+        assert_eq!(frames, 3);
+    }
+}
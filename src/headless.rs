@@ -0,0 +1,506 @@
+use std::{fmt::Write as _, fs, path::PathBuf, process::ExitCode};
+
+use anyhow::{ensure, Context, Result};
+use clap::Args;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use vek::Vec2;
+
+use crate::{
+    config::parse_window_size,
+    frames::{FrameFormat, FrameParameters, FrameRecorder},
+    particle_life::{
+        classify::{classify, CLASSIFICATION_WINDOW},
+        clusters::{self, ClusterParameters},
+        cpu::{self, CpuWorld},
+        metrics::Metrics,
+        presets::Preset,
+        rdf::{RadialDistribution, RdfParameters},
+        scenario::{simulate, Check, Scenario},
+        scene::Scene,
+        snapshot::{Import, SnapshotFormat},
+        trajectory::{TrajectoryFormat, TrajectoryParameters, TrajectoryRecorder},
+        ParticleKind, RuleGenerationParameters, Rules,
+    },
+    screenshot,
+};
+
+/// Exit status when the particles' positions or velocities stop being finite.
+pub const EXIT_NON_FINITE: u8 = 3;
+/// Exit status when a scenario's checks fail.
+pub const EXIT_SCENARIO_FAILED: u8 = 4;
+
+/// Run the simulation on the CPU, without opening a window, and write
+/// snapshots, metrics and the final rules to disk.
+///
+/// Exits with status 1 if anything goes wrong, 2 if the arguments are invalid,
+/// 3 if the particles' positions or velocities stop being finite and 4 if a
+/// scenario's checks fail.
+#[derive(Args)]
+pub struct RunArgs {
+    /// Scenario file to run, which sets up the world, changes its settings
+    /// partway through and checks the metrics at given steps.
+    #[arg(
+        long,
+        conflicts_with_all = ["preset", "seed", "particles", "species", "world_size", "steps"]
+    )]
+    scenario: Option<PathBuf>,
+
+    /// Screenshot to restore the particles, seed and rules from.
+    #[arg(
+        long,
+        conflicts_with_all = ["preset", "seed", "particles", "species", "world_size", "scenario"]
+    )]
+    scene: Option<PathBuf>,
+
+    /// CSV or PLY file to take the particles' positions, velocities and kinds
+    /// from, instead of placing them at random. Positions are scaled to fit
+    /// the world.
+    #[arg(long, conflicts_with_all = ["particles", "scenario", "scene"])]
+    import: Option<PathBuf>,
+
+    /// Preset file to take the rules and settings from. Without one, rules are
+    /// generated from the seed.
+    #[arg(long)]
+    preset: Option<PathBuf>,
+
+    /// Seed for the initial particle positions, and the rules if no preset is
+    /// given.
+    #[arg(long)]
+    seed: Option<u64>,
+
+    #[arg(long, default_value_t = 2000)]
+    particles: usize,
+
+    /// Number of kinds of particle, up to 8.
+    #[arg(long, default_value_t = ParticleKind::MAX)]
+    species: u32,
+
+    /// Size of the world, such as 3072x2304. Defaults to a size that gives the
+    /// particles the same density as in the viewer.
+    #[arg(long, value_parser = parse_world_size)]
+    world_size: Option<Vec2<f32>>,
+
+    #[arg(long, default_value_t = 1000)]
+    steps: usize,
+
+    /// Steps between each snapshot of the particles. The final step is always
+    /// written.
+    #[arg(long)]
+    snapshot_interval: Option<usize>,
+
+    /// Formats to write each snapshot in, separated by commas.
+    #[arg(long, value_enum, value_delimiter = ',', default_value = "csv")]
+    snapshot_format: Vec<SnapshotFormat>,
+
+    /// Also write the clusters of particles at each snapshot, with each
+    /// cluster's size and how many of each kind it holds.
+    #[arg(long)]
+    clusters: bool,
+
+    /// Also write the pair correlation function g(r) for every pair of kinds
+    /// at each snapshot.
+    #[arg(long)]
+    rdf: bool,
+
+    /// Formats to record the paths of the particles in, separated by commas,
+    /// as trajectories.csv or trajectories.bin. Paths carry on across the
+    /// edges of the world instead of wrapping, and follow up to 1000
+    /// particles spread over the IDs.
+    #[arg(long, value_enum, value_delimiter = ',')]
+    trajectories: Vec<TrajectoryFormat>,
+
+    /// Steps between each frame of the trajectories.
+    #[arg(long, default_value_t = 10)]
+    trajectory_interval: u64,
+
+    /// Steps between each row written to the metrics file.
+    #[arg(long, default_value_t = 1)]
+    metrics_interval: usize,
+
+    /// Also write a screenshot of the final step. It holds the seed and the
+    /// rules and settings the run started with, so passing it to --scene
+    /// starts the same run again, though without any imported particles or
+    /// the changes a scenario makes partway through.
+    #[arg(long)]
+    screenshot: bool,
+
+    /// Record frames, either as numbered PNGs in the frames directory, as a
+    /// frames.y4m stream for making videos or as a frames.gif animation.
+    #[arg(long, value_enum)]
+    frames: Option<FrameFormat>,
+
+    /// Steps between each frame.
+    #[arg(long, default_value_t = 1)]
+    frame_interval: u64,
+
+    /// Frame rate written into Y4M streams and GIFs.
+    #[arg(long, default_value_t = 30)]
+    fps: u32,
+
+    /// Number of colours in each frame of a GIF, up to 256.
+    #[arg(long, default_value_t = 64)]
+    palette_size: u16,
+
+    /// Size of screenshots and frames, such as 1024x768.
+    #[arg(long, value_parser = parse_window_size, default_value = "1024x768")]
+    image_size: [u32; 2],
+
+    /// Directory to write the results to.
+    #[arg(long, default_value = "run")]
+    output: PathBuf,
+}
+
+/// Parses a world size given as width by height, such as "3072x2304".
+pub fn parse_world_size(text: &str) -> Result<Vec2<f32>, String> {
+    let parse = |value: &str| {
+        value
+            .trim()
+            .parse::<f32>()
+            .ok()
+            .filter(|v| v.is_finite() && *v > 0.0)
+    };
+
+    text.split_once(['x', 'X'])
+        .and_then(|(width, height)| Some(Vec2::new(parse(width)?, parse(height)?)))
+        .ok_or_else(|| "expected WIDTHxHEIGHT with both greater than zero".to_owned())
+}
+
+pub fn run(args: &RunArgs) -> Result<ExitCode> {
+    ensure!(args.particles > 0, "particles must be at least 1");
+    ensure!(
+        args.metrics_interval > 0,
+        "metrics interval must be at least 1"
+    );
+    ensure!(
+        args.snapshot_interval != Some(0),
+        "snapshot interval must be at least 1"
+    );
+    ensure!(
+        args.trajectory_interval > 0,
+        "trajectory interval must be at least 1"
+    );
+
+    let scenario = args.scenario.as_deref().map(Scenario::load).transpose()?;
+
+    let Start {
+        name,
+        seed,
+        species,
+        mut world,
+        mut rules,
+        steps,
+    } = start(args, scenario.as_ref())?;
+    let world_size = world.world_size();
+    let initial = Preset::new(&name, world.settings(), &rules);
+
+    fs::create_dir_all(&args.output)
+        .with_context(|| format!("creating {}", args.output.display()))?;
+
+    let mut metrics_csv = format!("step,{}\n", Metrics::csv_header());
+    let mut recordings = Recordings::start(args, &world)?;
+
+    let simulation = simulate(&mut world, &mut rules, scenario.as_ref(), steps, |report| {
+        let step = report.step;
+        if step % args.metrics_interval == 0 {
+            writeln!(metrics_csv, "{step},{}", report.metrics.csv_row())?;
+        }
+
+        if !report.finite {
+            eprintln!("Particles became non-finite at step {step}");
+            return write_snapshot(args, step, report.world);
+        }
+
+        for check in &report.checks {
+            let status = if check.passed { "ok" } else { "FAIL" };
+            println!("{status}: {}", check.description);
+        }
+
+        let snapshot_due = args
+            .snapshot_interval
+            .is_some_and(|interval| step % interval == 0);
+        if snapshot_due || step == steps {
+            write_snapshot(args, step, report.world)?;
+        }
+        recordings.record(step, report.world)
+    })?;
+
+    recordings.finish(args)?;
+
+    fs::write(args.output.join("metrics.csv"), metrics_csv)?;
+
+    let final_rules = Preset::new(&name, world.settings(), &rules);
+    fs::write(args.output.join("final.toml"), final_rules.to_toml())?;
+
+    if args.screenshot {
+        let image = screenshot::render_world(world.particles(), world_size, args.image_size);
+        let scene = Scene {
+            seed,
+            particles: world.particles().len(),
+            species,
+            world_size,
+            preset: initial,
+        };
+        screenshot::save(&args.output.join("final.png"), &image, Some(&scene))?;
+    }
+
+    let history = &simulation.history;
+    let window = &history[history.len().saturating_sub(CLASSIFICATION_WINDOW)..];
+    match classify(window) {
+        Some(classification) => println!(
+            "Behaviour: {} ({:.0}% confidence)",
+            classification.behaviour.name(),
+            classification.confidence * 100.0
+        ),
+        None => println!("Behaviour: unknown, too few steps to judge"),
+    }
+    println!("Wrote results to {}", args.output.display());
+
+    if simulation.non_finite_step.is_some() {
+        Ok(ExitCode::from(EXIT_NON_FINITE))
+    } else if scenario.is_some() {
+        Ok(report_checks(&simulation.checks))
+    } else {
+        Ok(ExitCode::SUCCESS)
+    }
+}
+
+fn report_checks(checks: &[Check]) -> ExitCode {
+    let failed = checks.iter().filter(|check| !check.passed).count();
+    if failed == 0 {
+        println!("Scenario passed");
+        ExitCode::SUCCESS
+    } else {
+        println!(
+            "Scenario failed: {failed} of {} checks failed",
+            checks.len()
+        );
+        ExitCode::from(EXIT_SCENARIO_FAILED)
+    }
+}
+
+fn start_frames(args: &RunArgs, format: FrameFormat) -> Result<FrameRecorder> {
+    let params = FrameParameters {
+        format,
+        interval: args.frame_interval,
+        size: args.image_size,
+        fps: args.fps,
+        palette_size: args.palette_size,
+        duration: None,
+    };
+    let path = match format {
+        FrameFormat::Png => args.output.join("frames"),
+        FrameFormat::Y4m => args.output.join("frames.y4m"),
+        FrameFormat::Gif => args.output.join("frames.gif"),
+    };
+    FrameRecorder::new(params, &path)
+}
+
+/// The frames and trajectories recorded as the world steps, if any were asked
+/// for.
+struct Recordings {
+    frames: Option<FrameRecorder>,
+    trajectories: Option<TrajectoryRecorder>,
+}
+
+impl Recordings {
+    /// Starts recording, with the world as it is before the first step as the
+    /// first frame.
+    fn start(args: &RunArgs, world: &CpuWorld) -> Result<Self> {
+        let mut frames = args
+            .frames
+            .map(|format| start_frames(args, format))
+            .transpose()?;
+        record_frame(frames.as_mut(), 0, world)?;
+
+        let trajectories = (!args.trajectories.is_empty()).then(|| {
+            let params = TrajectoryParameters {
+                interval: args.trajectory_interval,
+                ..Default::default()
+            };
+            TrajectoryRecorder::new(params, 0, world.particles(), world.world_size())
+        });
+
+        Ok(Recordings {
+            frames,
+            trajectories,
+        })
+    }
+
+    fn record(&mut self, step: usize, world: &CpuWorld) -> Result<()> {
+        record_frame(self.frames.as_mut(), step, world)?;
+        if let Some(trajectories) = &mut self.trajectories {
+            trajectories.record(step as u64, world.particles());
+        }
+        Ok(())
+    }
+
+    fn finish(self, args: &RunArgs) -> Result<()> {
+        if let Some(frames) = self.frames {
+            println!(
+                "Wrote {} frames to {}",
+                frames.num_frames(),
+                frames.path().display()
+            );
+            frames.finish()?;
+        }
+
+        if let Some(trajectories) = &self.trajectories {
+            for format in &args.trajectories {
+                let path = args
+                    .output
+                    .join(format!("trajectories.{}", format.extension()));
+                trajectories
+                    .export(&path, *format)
+                    .with_context(|| format!("writing {}", path.display()))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Records a frame of the whole world, if one is due at `step`.
+fn record_frame(frames: Option<&mut FrameRecorder>, step: usize, world: &CpuWorld) -> Result<()> {
+    let step = step as u64;
+    match frames {
+        Some(frames) if frames.is_due(step) => {
+            let image =
+                screenshot::render_world(world.particles(), world.world_size(), frames.size());
+            frames.write(step, &image)
+        }
+        _ => Ok(()),
+    }
+}
+
+/// The world a run starts from.
+struct Start {
+    name: String,
+    seed: u64,
+    species: u32,
+    world: CpuWorld,
+    rules: Rules,
+    steps: usize,
+}
+
+/// Sets up the world from the scenario, if there is one, or else from the
+/// command line.
+fn start(args: &RunArgs, scenario: Option<&Scenario>) -> Result<Start> {
+    if let Some(scenario) = scenario {
+        println!("Scenario: {}", scenario.name);
+        if !scenario.description.is_empty() {
+            println!("{}", scenario.description);
+        }
+        let (world, rules) = scenario.create_world();
+        return Ok(Start {
+            name: scenario.name.clone(),
+            seed: scenario.seed,
+            species: scenario.species,
+            world,
+            rules,
+            steps: scenario.steps,
+        });
+    }
+
+    let scene = args
+        .scene
+        .as_deref()
+        .map(screenshot::load_scene)
+        .transpose()?;
+
+    let seed = scene
+        .as_ref()
+        .map(|scene| scene.seed)
+        .or(args.seed)
+        .unwrap_or_else(|| rand::rng().random());
+    println!("Seed: {seed}");
+
+    ensure!(
+        (1..=ParticleKind::MAX).contains(&args.species),
+        "species must be between 1 and {}, got {}",
+        ParticleKind::MAX,
+        args.species
+    );
+    let species = scene.as_ref().map_or(args.species, |scene| scene.species);
+
+    let preset = match &scene {
+        Some(scene) => Some(scene.preset.clone()),
+        None => args.preset.as_deref().map(Preset::load).transpose()?,
+    };
+    let rules = preset.as_ref().map_or_else(
+        || {
+            let params = RuleGenerationParameters {
+                species,
+                ..Default::default()
+            };
+            Rules::new_random_with_rng(&params, &mut StdRng::seed_from_u64(seed))
+        },
+        |preset| preset.rules,
+    );
+
+    let import = args
+        .import
+        .as_deref()
+        .map(|path| Import::load(path, species))
+        .transpose()?;
+
+    let particles = match (&scene, &import) {
+        (Some(scene), _) => scene.particles,
+        (None, Some(import)) => import.num_particles(),
+        (None, None) => args.particles,
+    };
+    let world_size = scene
+        .as_ref()
+        .map(|scene| scene.world_size)
+        .or(args.world_size)
+        .unwrap_or_else(|| cpu::default_world_size(particles));
+    let mut world = match &import {
+        Some(import) => CpuWorld::from_particles(import.fit(world_size), world_size),
+        None => CpuWorld::from_seed(particles, world_size, species, seed),
+    };
+    if let Some(preset) = &preset {
+        preset.apply_settings(world.settings());
+    }
+
+    let name = preset.map_or_else(|| format!("Seed {seed}"), |preset| preset.name);
+    Ok(Start {
+        name,
+        seed,
+        species,
+        world,
+        rules,
+        steps: args.steps,
+    })
+}
+
+fn write_snapshot(args: &RunArgs, step: usize, world: &CpuWorld) -> Result<()> {
+    for format in &args.snapshot_format {
+        let path = args
+            .output
+            .join(format!("snapshot_{step:06}.{}", format.extension()));
+        fs::write(&path, format.encode(world.particles(), world.world_size()))
+            .with_context(|| format!("writing {}", path.display()))?;
+    }
+
+    if args.clusters {
+        let clustering = clusters::find_clusters(
+            world.particles(),
+            world.world_size(),
+            &ClusterParameters::default(),
+        );
+        let path = args.output.join(format!("clusters_{step:06}.csv"));
+        fs::write(&path, clustering.to_csv())
+            .with_context(|| format!("writing {}", path.display()))?;
+    }
+
+    if args.rdf {
+        let rdf = RadialDistribution::measure(
+            world.particles(),
+            world.world_size(),
+            &RdfParameters::default(),
+        );
+        let path = args.output.join(format!("rdf_{step:06}.csv"));
+        fs::write(&path, rdf.to_csv()).with_context(|| format!("writing {}", path.display()))?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,11 @@
+//! The simulation, and everything that works with its particles, shared by the
+//! viewer and the headless tools. Only the GPU world depends on the platform,
+//! so the rest is built and tested everywhere, not only where the viewer
+//! builds.
+
+// The library only exists to be shared with the binary in this crate, so its
+// errors and panics are no more part of a public API than the binary's are.
+#![allow(clippy::missing_errors_doc, clippy::missing_panics_doc)]
+
+pub mod particle_life;
+pub mod raster;
@@ -0,0 +1,243 @@
+use super::metrics::Metrics;
+
+/// Fewer samples than this aren't enough to judge a world by.
+pub const MIN_SAMPLES: usize = 60;
+
+/// Number of the most recent samples that a world's behaviour is judged on,
+/// about five seconds in the viewer.
+pub const CLASSIFICATION_WINDOW: usize = 300;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Behaviour {
+    /// Nothing moves and there is no structure.
+    Frozen,
+    /// Particles drift about evenly, with no structure.
+    Gas,
+    /// Particles have settled into clumps that don't move.
+    Crystals,
+    /// Clumps that keep moving.
+    Organisms,
+    /// Motion that rises and falls regularly.
+    Oscillating,
+    /// Motion that changes erratically, or values that have blown up.
+    Chaotic,
+}
+
+impl Behaviour {
+    pub const ALL: [Behaviour; 6] = [
+        Behaviour::Frozen,
+        Behaviour::Gas,
+        Behaviour::Crystals,
+        Behaviour::Organisms,
+        Behaviour::Oscillating,
+        Behaviour::Chaotic,
+    ];
+
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Behaviour> {
+        Self::ALL.into_iter().find(|b| b.name() == name)
+    }
+
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Behaviour::Frozen => "frozen",
+            Behaviour::Gas => "gas",
+            Behaviour::Crystals => "crystals",
+            Behaviour::Organisms => "organisms",
+            Behaviour::Oscillating => "oscillating",
+            Behaviour::Chaotic => "chaotic",
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Classification {
+    pub behaviour: Behaviour,
+    /// How strongly the world matches `behaviour` rather than the others, from
+    /// 0 to 1.
+    pub confidence: f32,
+}
+
+/// Classifies a world from its metrics at consecutive steps, oldest first.
+/// Each behaviour is scored by how well a few features (how much is moving,
+/// how clumped it is, how steady and how periodic the motion is) fit it, and
+/// the best score wins. Returns `None` if there are too few samples.
+#[must_use]
+pub fn classify(history: &[Metrics]) -> Option<Classification> {
+    if history.len() < MIN_SAMPLES {
+        return None;
+    }
+
+    let finite = history.iter().all(|m| {
+        m.mean_speed.is_finite() && m.kinetic_energy.is_finite() && m.dispersion.is_finite()
+    });
+    if !finite {
+        return Some(Classification {
+            behaviour: Behaviour::Chaotic,
+            confidence: 1.0,
+        });
+    }
+
+    let speeds: Vec<f32> = history.iter().map(|m| m.mean_speed).collect();
+    let mean_speed = mean(&speeds);
+    let dispersion = mean(&history.iter().map(|m| m.dispersion).collect::<Vec<_>>());
+
+    let moving = ramp(mean_speed, 0.05, 0.3);
+    let still = 1.0 - moving;
+    let clumped = ramp(dispersion, 1.5, 4.0);
+    let variation = std_dev(&speeds) / mean_speed.max(f32::EPSILON);
+    let steady = 1.0 - ramp(variation, 0.05, 0.3);
+    let periodic = ramp(periodicity(&speeds), 0.3, 0.8);
+
+    let score = |behaviour| match behaviour {
+        Behaviour::Frozen => still * (1.0 - clumped),
+        Behaviour::Crystals => still * clumped,
+        Behaviour::Gas => moving * (1.0 - clumped) * (1.0 - periodic),
+        Behaviour::Organisms => moving * clumped * steady * (1.0 - periodic),
+        Behaviour::Oscillating => moving * periodic,
+        Behaviour::Chaotic => moving * (1.0 - steady) * (1.0 - periodic),
+    };
+
+    let scores = Behaviour::ALL.map(score);
+    let total: f32 = scores.iter().sum();
+    let (best, best_score) = Behaviour::ALL
+        .into_iter()
+        .zip(scores)
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .unwrap();
+
+    Some(Classification {
+        behaviour: best,
+        confidence: if total > 0.0 { best_score / total } else { 0.0 },
+    })
+}
+
+/// 0 below `low`, 1 above `high`, and linear in between.
+fn ramp(value: f32, low: f32, high: f32) -> f32 {
+    ((value - low) / (high - low)).clamp(0.0, 1.0)
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn mean(values: &[f32]) -> f32 {
+    values.iter().sum::<f32>() / values.len() as f32
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn std_dev(values: &[f32]) -> f32 {
+    let mean = mean(values);
+    let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f32>() / values.len() as f32;
+    variance.sqrt()
+}
+
+/// The strongest autocorrelation of `values` at any lag past the point where
+/// it first turns negative: near 1 for a regular oscillation, near 0 for noise.
+/// Requiring it to turn negative first stops a world that is slowly settling,
+/// which correlates with itself at every lag, from looking periodic.
+#[allow(clippy::cast_precision_loss)]
+fn periodicity(values: &[f32]) -> f32 {
+    let mean = mean(values);
+    let centred: Vec<f32> = values.iter().map(|v| v - mean).collect();
+    let variance: f32 = centred.iter().map(|v| v * v).sum();
+    if variance <= f32::EPSILON {
+        return 0.0;
+    }
+
+    let autocorrelation = |lag: usize| {
+        let covariance: f32 = centred
+            .iter()
+            .zip(&centred[lag..])
+            .map(|(a, b)| a * b)
+            .sum();
+        // Scale up to make up for the pairs lost off the end
+        covariance / variance * values.len() as f32 / (values.len() - lag) as f32
+    };
+
+    let max_lag = values.len() / 2;
+    let Some(first_negative) = (1..=max_lag).find(|lag| autocorrelation(*lag) < 0.0) else {
+        return 0.0;
+    };
+
+    (first_negative..=max_lag)
+        .map(autocorrelation)
+        .fold(0.0, f32::max)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::TAU;
+
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::{classify, Behaviour, CLASSIFICATION_WINDOW, MIN_SAMPLES};
+    use crate::particle_life::metrics::Metrics;
+
+    /// A full window of metrics with the given mean speed at each step and a
+    /// fixed dispersion.
+    #[allow(clippy::cast_precision_loss)]
+    fn history(mut speed: impl FnMut(f32) -> f32, dispersion: f32) -> Vec<Metrics> {
+        (0..CLASSIFICATION_WINDOW)
+            .map(|step| Metrics {
+                mean_speed: speed(step as f32),
+                dispersion,
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    fn behaviour(history: &[Metrics]) -> Behaviour {
+        classify(history).unwrap().behaviour
+    }
+
+    #[test]
+    fn too_few_samples() {
+        let history = history(|_| 0.5, 1.0);
+        assert!(classify(&history[..MIN_SAMPLES - 1]).is_none());
+        assert!(classify(&history[..MIN_SAMPLES]).is_some());
+    }
+
+    #[test]
+    fn frozen() {
+        assert_eq!(behaviour(&history(|_| 0.0, 1.0)), Behaviour::Frozen);
+    }
+
+    #[test]
+    fn crystals() {
+        assert_eq!(behaviour(&history(|_| 0.01, 5.0)), Behaviour::Crystals);
+    }
+
+    #[test]
+    fn gas() {
+        assert_eq!(behaviour(&history(|_| 0.5, 1.0)), Behaviour::Gas);
+    }
+
+    #[test]
+    fn organisms() {
+        let classification = classify(&history(|_| 0.5, 5.0)).unwrap();
+        assert_eq!(classification.behaviour, Behaviour::Organisms);
+        assert!(classification.confidence > 0.9);
+    }
+
+    #[test]
+    fn oscillating() {
+        let speed = |step: f32| 0.5 + 0.2 * (TAU * step / 20.0).sin();
+        assert_eq!(behaviour(&history(speed, 5.0)), Behaviour::Oscillating);
+    }
+
+    #[test]
+    fn chaotic() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let speed = |_| rng.random_range(0.1..0.9);
+        assert_eq!(behaviour(&history(speed, 2.0)), Behaviour::Chaotic);
+    }
+
+    #[test]
+    fn non_finite_values_are_chaotic() {
+        let mut history = history(|_| 0.0, 1.0);
+        history[100].mean_speed = f32::NAN;
+
+        let classification = classify(&history).unwrap();
+        assert_eq!(classification.behaviour, Behaviour::Chaotic);
+        assert!((classification.confidence - 1.0).abs() < f32::EPSILON);
+    }
+}
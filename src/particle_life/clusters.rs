@@ -0,0 +1,330 @@
+use std::fmt::Write as _;
+
+use palette::{FromColor, Hsl, Srgb};
+use vek::Vec2;
+
+use super::{
+    cpu::{wrapped_delta, Grid},
+    Particle, ParticleKind,
+};
+
+const NUM_KINDS: usize = ParticleKind::MAX as usize;
+
+#[derive(Clone)]
+pub struct ClusterParameters {
+    /// Particles closer than this are neighbours.
+    pub radius: f32,
+    /// Number of neighbours, counting itself, that a particle needs to be in
+    /// the core of a cluster.
+    pub min_points: usize,
+}
+
+impl Default for ClusterParameters {
+    fn default() -> Self {
+        ClusterParameters {
+            radius: 15.0,
+            min_points: 5,
+        }
+    }
+}
+
+pub struct Cluster {
+    /// Indices of the particles in the cluster.
+    pub particles: Vec<usize>,
+    /// Number of particles of each kind in the cluster.
+    pub composition: [usize; NUM_KINDS],
+}
+
+impl Cluster {
+    #[must_use]
+    pub fn size(&self) -> usize {
+        self.particles.len()
+    }
+}
+
+pub struct Clustering {
+    /// The cluster each particle belongs to, if any, as an index into
+    /// `clusters`.
+    pub labels: Vec<Option<usize>>,
+    /// Largest first.
+    pub clusters: Vec<Cluster>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Label {
+    Unvisited,
+    Noise,
+    Cluster(usize),
+}
+
+/// Groups particles with DBSCAN, measuring distances across the edges of the
+/// world so that a cluster straddling an edge stays in one piece. Particles
+/// that aren't dense enough to belong to any cluster are left unlabelled.
+#[must_use]
+pub fn find_clusters(
+    particles: &[Particle],
+    world_size: Vec2<f32>,
+    params: &ClusterParameters,
+) -> Clustering {
+    let grid = Grid::new(particles, world_size, params.radius);
+    let radius_squared = params.radius * params.radius;
+
+    let neighbours = |index: usize, out: &mut Vec<usize>| {
+        out.clear();
+        let position = particles[index].position;
+        grid.for_each_neighbour(position, |other| {
+            let delta = wrapped_delta(position, particles[other].position, world_size);
+            if delta.magnitude_squared() <= radius_squared {
+                out.push(other);
+            }
+        });
+    };
+
+    let mut labels = vec![Label::Unvisited; particles.len()];
+    let mut num_clusters = 0;
+    let mut found = Vec::new();
+    let mut queue = Vec::new();
+
+    for start in 0..particles.len() {
+        if labels[start] != Label::Unvisited {
+            continue;
+        }
+
+        neighbours(start, &mut found);
+        if found.len() < params.min_points {
+            labels[start] = Label::Noise;
+            continue;
+        }
+
+        let cluster = Label::Cluster(num_clusters);
+        num_clusters += 1;
+        labels[start] = cluster;
+        queue.extend_from_slice(&found);
+
+        while let Some(index) = queue.pop() {
+            match labels[index] {
+                // Too sparse to be a core particle, but on the edge of this
+                // cluster
+                Label::Noise => labels[index] = cluster,
+                Label::Unvisited => {
+                    labels[index] = cluster;
+                    neighbours(index, &mut found);
+                    if found.len() >= params.min_points {
+                        queue.extend_from_slice(&found);
+                    }
+                }
+                Label::Cluster(_) => (),
+            }
+        }
+    }
+
+    // Number the clusters largest first
+    let mut sizes = vec![0_usize; num_clusters];
+    for label in &labels {
+        if let Label::Cluster(cluster) = label {
+            sizes[*cluster] += 1;
+        }
+    }
+    let mut order: Vec<usize> = (0..num_clusters).collect();
+    order.sort_by_key(|cluster| std::cmp::Reverse(sizes[*cluster]));
+    let mut new_index = vec![0; num_clusters];
+    for (new, old) in order.iter().enumerate() {
+        new_index[*old] = new;
+    }
+
+    let labels: Vec<Option<usize>> = labels
+        .iter()
+        .map(|label| match label {
+            Label::Cluster(cluster) => Some(new_index[*cluster]),
+            _ => None,
+        })
+        .collect();
+
+    let mut clusters: Vec<Cluster> = order
+        .iter()
+        .map(|old| Cluster {
+            particles: Vec::with_capacity(sizes[*old]),
+            composition: [0; NUM_KINDS],
+        })
+        .collect();
+    for (index, label) in labels.iter().enumerate() {
+        if let Some(cluster) = label {
+            clusters[*cluster].particles.push(index);
+            clusters[*cluster].composition[particles[index].kind.index()] += 1;
+        }
+    }
+
+    Clustering { labels, clusters }
+}
+
+impl Clustering {
+    /// Number of particles that aren't in any cluster.
+    #[must_use]
+    pub fn unclustered(&self) -> usize {
+        self.labels.iter().filter(|label| label.is_none()).count()
+    }
+
+    /// One row per cluster, largest first, with its size and how many
+    /// particles of each kind it holds.
+    #[must_use]
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("cluster,size");
+        for kind in ParticleKind::all() {
+            write!(csv, ",kind_{}", kind.index()).unwrap();
+        }
+        csv.push('\n');
+
+        for (index, cluster) in self.clusters.iter().enumerate() {
+            write!(csv, "{index},{}", cluster.size()).unwrap();
+            for count in cluster.composition {
+                write!(csv, ",{count}").unwrap();
+            }
+            csv.push('\n');
+        }
+
+        csv
+    }
+
+    /// A colour per particle, packed as ABGR like vertex colours, that gives
+    /// each cluster its own hue and leaves unclustered particles dark grey.
+    /// Each cluster's hue comes from its lowest particle index, so colours
+    /// mostly stay put as clusters are recomputed.
+    #[must_use]
+    pub fn colors(&self) -> Vec<u32> {
+        let cluster_colors: Vec<u32> = self
+            .clusters
+            .iter()
+            .map(|cluster| {
+                let first = cluster.particles.iter().min().copied().unwrap_or(0);
+                hue_color(first)
+            })
+            .collect();
+
+        self.labels
+            .iter()
+            .map(|label| label.map_or(UNCLUSTERED_COLOR, |c| cluster_colors[c]))
+            .collect()
+    }
+}
+
+const UNCLUSTERED_COLOR: u32 = 0xff30_3030;
+
+/// Spreads hues around the colour wheel using the golden ratio, so that
+/// nearby indices get very different colours.
+#[allow(clippy::cast_precision_loss)]
+fn hue_color(index: usize) -> u32 {
+    const GOLDEN_RATIO_CONJUGATE: f32 = 0.618_034;
+
+    let hue = (index as f32 * GOLDEN_RATIO_CONJUGATE).fract();
+    let rgb: Srgb<u8> = Srgb::from_color(Hsl::new_srgb(360.0 * hue, 1.0, 0.6)).into_format();
+
+    0xff00_0000 | u32::from(rgb.blue) << 16 | u32::from(rgb.green) << 8 | u32::from(rgb.red)
+}
+
+#[cfg(test)]
+mod tests {
+    use vek::Vec2;
+
+    use super::{find_clusters, ClusterParameters};
+    use crate::particle_life::{Particle, ParticleKind};
+
+    const WORLD_SIZE: Vec2<f32> = Vec2::new(100.0, 80.0);
+
+    const PARAMS: ClusterParameters = ClusterParameters {
+        radius: 5.0,
+        min_points: 3,
+    };
+
+    fn particles(positions: &[(f32, f32, u32)]) -> Vec<Particle> {
+        positions
+            .iter()
+            .zip(0..)
+            .map(|(&(x, y, kind), id)| Particle {
+                position: Vec2::new(x, y),
+                velocity: Vec2::zero(),
+                kind: ParticleKind(kind),
+                neighbours: 0,
+                id,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn cluster_across_the_world_edge_stays_whole() {
+        let particles = particles(&[
+            // Straddling the left and right edges
+            (1.0, 40.0, 0),
+            (99.0, 40.0, 0),
+            (2.0, 42.0, 1),
+            (98.0, 38.0, 1),
+            (0.5, 37.0, 2),
+            // Straddling the top and bottom edges
+            (50.0, 1.0, 3),
+            (51.0, 79.0, 3),
+            (49.0, 78.5, 3),
+        ]);
+        let clustering = find_clusters(&particles, WORLD_SIZE, &PARAMS);
+
+        assert_eq!(clustering.clusters.len(), 2);
+        assert_eq!(clustering.clusters[0].particles, [0, 1, 2, 3, 4]);
+        assert_eq!(clustering.clusters[0].composition[..4], [2, 2, 1, 0]);
+        assert_eq!(clustering.clusters[1].particles, [5, 6, 7]);
+        assert_eq!(clustering.clusters[1].composition[3], 3);
+        assert_eq!(clustering.unclustered(), 0);
+    }
+
+    #[test]
+    fn sparse_particles_are_noise() {
+        let particles = particles(&[
+            (20.0, 20.0, 0),
+            (22.0, 20.0, 0),
+            (20.0, 22.0, 0),
+            // Isolated
+            (60.0, 60.0, 1),
+            // A pair, one short of a cluster
+            (80.0, 10.0, 2),
+            (82.0, 10.0, 2),
+            // Within reach of the cluster's edge, but without enough
+            // neighbours of its own to extend it
+            (26.5, 20.0, 3),
+        ]);
+        let clustering = find_clusters(&particles, WORLD_SIZE, &PARAMS);
+
+        assert_eq!(clustering.clusters.len(), 1);
+        assert_eq!(
+            clustering.labels,
+            [Some(0), Some(0), Some(0), None, None, None, Some(0)]
+        );
+        assert_eq!(clustering.unclustered(), 3);
+
+        let colors = clustering.colors();
+        assert_eq!(colors[3], super::UNCLUSTERED_COLOR);
+        assert_ne!(colors[0], super::UNCLUSTERED_COLOR);
+        assert_eq!(colors[0], colors[6]);
+    }
+
+    #[test]
+    fn csv_has_a_row_per_cluster() {
+        let particles = particles(&[
+            (10.0, 10.0, 0),
+            (11.0, 10.0, 1),
+            (10.0, 11.0, 1),
+            (50.0, 50.0, 2),
+            (51.0, 50.0, 2),
+            (50.0, 51.0, 2),
+            (51.0, 51.0, 2),
+            (90.0, 70.0, 5),
+        ]);
+        let csv = find_clusters(&particles, WORLD_SIZE, &PARAMS).to_csv();
+        let lines: Vec<&str> = csv.lines().collect();
+
+        assert_eq!(
+            lines,
+            [
+                "cluster,size,kind_0,kind_1,kind_2,kind_3,kind_4,kind_5,kind_6,kind_7",
+                "0,4,0,0,4,0,0,0,0,0",
+                "1,3,1,2,0,0,0,0,0,0",
+            ]
+        );
+    }
+}
@@ -0,0 +1,456 @@
+use std::{num::NonZeroUsize, thread};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use vek::Vec2;
+
+use super::{Particle, ParticleKind, Rules, ShaderGlobalConstants};
+
+/// Runs the simulation on the CPU, for headless tools that have no GPU to work
+/// with. Each step follows `particle_life.hlsl` so that a `CpuWorld` behaves
+/// like a `World` given the same rules and settings. Rather than testing every
+/// pair of particles it bins them into a grid with cells at least as large as
+/// the longest rule distance; pairs further apart than that don't interact.
+pub struct CpuWorld {
+    settings: ShaderGlobalConstants,
+    particles: Vec<Particle>,
+}
+
+impl CpuWorld {
+    pub fn new(num_particles: usize, size: Vec2<f32>, rng: &mut impl Rng) -> Self {
+        Self::with_species(num_particles, size, ParticleKind::MAX, rng)
+    }
+
+    /// Like `new`, but with particles of only the first `species` kinds.
+    pub fn with_species(
+        num_particles: usize,
+        size: Vec2<f32>,
+        species: u32,
+        rng: &mut impl Rng,
+    ) -> Self {
+        CpuWorld {
+            settings: ShaderGlobalConstants::new(num_particles, size),
+            particles: random_particles(num_particles, size, species, rng),
+        }
+    }
+
+    /// Places the particles from a generator of their own seeded with `seed`,
+    /// the same way the viewer's `World` does, so that a seed gives the same
+    /// world in both.
+    #[must_use]
+    pub fn from_seed(num_particles: usize, size: Vec2<f32>, species: u32, seed: u64) -> Self {
+        Self::with_species(
+            num_particles,
+            size,
+            species,
+            &mut StdRng::seed_from_u64(seed),
+        )
+    }
+
+    /// A world of the given size holding `particles`, such as ones that were
+    /// imported from a file.
+    #[must_use]
+    pub fn from_particles(particles: Vec<Particle>, size: Vec2<f32>) -> Self {
+        CpuWorld {
+            settings: ShaderGlobalConstants::new(particles.len(), size),
+            particles,
+        }
+    }
+
+    pub fn settings(&mut self) -> &mut ShaderGlobalConstants {
+        &mut self.settings
+    }
+
+    #[must_use]
+    pub fn world_size(&self) -> Vec2<f32> {
+        Vec2::from(self.settings.world_size)
+    }
+
+    #[must_use]
+    pub fn particles(&self) -> &[Particle] {
+        &self.particles
+    }
+
+    pub fn step(&mut self, rules: &Rules) {
+        let grid = Grid::new(
+            &self.particles,
+            Vec2::from(self.settings.world_size),
+            interaction_distance(rules),
+        );
+
+        let mut next = self.particles.clone();
+
+        let threads = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+        let chunk_size = next.len().div_ceil(threads).max(1);
+
+        thread::scope(|scope| {
+            for (chunk_index, chunk) in next.chunks_mut(chunk_size).enumerate() {
+                let grid = &grid;
+                let particles = &self.particles;
+                let settings = &self.settings;
+
+                scope.spawn(move || {
+                    for (i, particle) in chunk.iter_mut().enumerate() {
+                        let index = chunk_index * chunk_size + i;
+                        *particle = update_particle(index, particles, grid, rules, settings);
+                    }
+                });
+            }
+        });
+
+        self.particles = next;
+    }
+}
+
+/// Number of particles per unit area in the viewer's default world: 50000
+/// particles in three times a 1024x768 window in each direction.
+const DEFAULT_DENSITY: f32 = 50_000.0 / (3072.0 * 2304.0);
+
+/// A 4:3 world size that gives `num_particles` the same density as the
+/// viewer's default world, so that rules behave similarly at smaller scales.
+#[allow(clippy::cast_precision_loss)]
+#[must_use]
+pub fn default_world_size(num_particles: usize) -> Vec2<f32> {
+    let area = num_particles as f32 / DEFAULT_DENSITY;
+    let width = (area * 4.0 / 3.0).sqrt();
+    Vec2::new(width, width * 0.75)
+}
+
+fn random_particles(
+    num_particles: usize,
+    size: Vec2<f32>,
+    species: u32,
+    rng: &mut impl Rng,
+) -> Vec<Particle> {
+    (0..num_particles)
+        .map(|id| Particle::new(u32::try_from(id).unwrap(), size, species, rng))
+        .collect()
+}
+
+/// The distance beyond which no rule has any effect.
+fn interaction_distance(rules: &Rules) -> f32 {
+    rules
+        .rules
+        .iter()
+        .map(|rule| rule.min_distance.max(rule.max_distance))
+        .fold(0.0, f32::max)
+}
+
+/// Returns the shortest vector from `from` to `to` in a world that wraps
+/// around at its edges.
+#[must_use]
+pub fn wrapped_delta(from: Vec2<f32>, to: Vec2<f32>, world_size: Vec2<f32>) -> Vec2<f32> {
+    let mut direction = to - from;
+
+    if direction.x > world_size.x * 0.5 {
+        direction.x -= world_size.x;
+    }
+    if direction.x < world_size.x * -0.5 {
+        direction.x += world_size.x;
+    }
+    if direction.y > world_size.y * 0.5 {
+        direction.y -= world_size.y;
+    }
+    if direction.y < world_size.y * -0.5 {
+        direction.y += world_size.y;
+    }
+
+    direction
+}
+
+fn update_particle(
+    index: usize,
+    particles: &[Particle],
+    grid: &Grid,
+    rules: &Rules,
+    settings: &ShaderGlobalConstants,
+) -> Particle {
+    let particle = particles[index];
+    let world_size = Vec2::from(settings.world_size);
+
+    let mut force = Vec2::zero();
+    let mut neighbours = 0;
+
+    grid.for_each_neighbour(particle.position, |other_index| {
+        if other_index == index {
+            return;
+        }
+
+        let other = &particles[other_index];
+        let rule = rules.get_rule(particle.kind, other.kind);
+
+        let direction = wrapped_delta(particle.position, other.position, world_size);
+        let distance = direction.magnitude();
+
+        // The shader would produce NaNs for particles on top of each other;
+        // skip them instead.
+        if distance > 0.0 {
+            force += direction / distance * rule.force_at(distance);
+        }
+        if distance < rule.max_distance {
+            neighbours += 1;
+        }
+    });
+
+    let mut velocity = particle.velocity;
+    velocity += force * settings.force_multiplier;
+    velocity *= settings.friction;
+
+    let mut position = particle.position + velocity;
+
+    if position.x < 0.0 {
+        position.x += world_size.x;
+    }
+    if position.x > world_size.x {
+        position.x -= world_size.x;
+    }
+    if position.y < 0.0 {
+        position.y += world_size.y;
+    }
+    if position.y > world_size.y {
+        position.y -= world_size.y;
+    }
+
+    Particle {
+        position,
+        velocity,
+        kind: particle.kind,
+        neighbours,
+        id: particle.id,
+    }
+}
+
+/// Particle indices bucketed by grid cell.
+pub(super) struct Grid {
+    cells: Vec2<usize>,
+    cell_size: Vec2<f32>,
+    /// `indices[cell_start[c]..cell_start[c + 1]]` are the particles in cell c.
+    cell_start: Vec<usize>,
+    indices: Vec<usize>,
+}
+
+impl Grid {
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss
+    )]
+    pub(super) fn new(particles: &[Particle], world_size: Vec2<f32>, min_cell_size: f32) -> Self {
+        let min_cell_size = min_cell_size.max(1.0);
+        let cells = Vec2::new(
+            ((world_size.x / min_cell_size) as usize).max(1),
+            ((world_size.y / min_cell_size) as usize).max(1),
+        );
+        let cell_size = world_size / Vec2::new(cells.x as f32, cells.y as f32);
+
+        let mut grid = Grid {
+            cells,
+            cell_size,
+            cell_start: vec![0; cells.x * cells.y + 1],
+            indices: vec![0; particles.len()],
+        };
+
+        // Counting sort of the particles by cell
+        let cell_of: Vec<usize> = particles
+            .iter()
+            .map(|p| grid.cell_index(grid.cell_coords(p.position)))
+            .collect();
+
+        for cell in &cell_of {
+            grid.cell_start[cell + 1] += 1;
+        }
+        for i in 1..grid.cell_start.len() {
+            grid.cell_start[i] += grid.cell_start[i - 1];
+        }
+
+        let mut next = grid.cell_start.clone();
+        for (particle_index, cell) in cell_of.iter().enumerate() {
+            grid.indices[next[*cell]] = particle_index;
+            next[*cell] += 1;
+        }
+
+        grid
+    }
+
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn cell_coords(&self, position: Vec2<f32>) -> Vec2<usize> {
+        // `as` saturates, so NaNs and negative values end up in cell 0
+        Vec2::new(
+            ((position.x / self.cell_size.x) as usize).min(self.cells.x - 1),
+            ((position.y / self.cell_size.y) as usize).min(self.cells.y - 1),
+        )
+    }
+
+    fn cell_index(&self, coords: Vec2<usize>) -> usize {
+        coords.y * self.cells.x + coords.x
+    }
+
+    /// Calls `f` with the index of every particle in the cell containing
+    /// `position` and the cells around it, wrapping at the edges of the world.
+    pub(super) fn for_each_neighbour(&self, position: Vec2<f32>, mut f: impl FnMut(usize)) {
+        let center = self.cell_coords(position);
+
+        for y in neighbour_offsets(self.cells.y) {
+            for x in neighbour_offsets(self.cells.x) {
+                let coords = Vec2::new(
+                    (center.x + self.cells.x + x - 1) % self.cells.x,
+                    (center.y + self.cells.y + y - 1) % self.cells.y,
+                );
+                let cell = self.cell_index(coords);
+
+                for &index in &self.indices[self.cell_start[cell]..self.cell_start[cell + 1]] {
+                    f(index);
+                }
+            }
+        }
+    }
+}
+
+/// Offsets (biased by one, so 0 is the previous cell) to the neighbouring
+/// cells along an axis. With fewer than three cells the neighbours overlap, so
+/// visit each cell only once.
+fn neighbour_offsets(num_cells: usize) -> std::ops::Range<usize> {
+    match num_cells {
+        1 => 1..2,
+        2 => 1..3,
+        _ => 0..3,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use vek::Vec2;
+
+    use super::{wrapped_delta, CpuWorld, Grid};
+    use crate::particle_life::{Particle, ParticleKind, Rule, Rules};
+
+    fn particle(id: u32, x: f32, y: f32) -> Particle {
+        Particle {
+            position: Vec2::new(x, y),
+            velocity: Vec2::zero(),
+            kind: ParticleKind(0),
+            neighbours: 0,
+            id,
+        }
+    }
+
+    fn neighbours(grid: &Grid, position: Vec2<f32>) -> Vec<usize> {
+        let mut found = Vec::new();
+        grid.for_each_neighbour(position, |index| found.push(index));
+        found.sort_unstable();
+        found
+    }
+
+    #[test]
+    fn grid_finds_neighbours_across_the_wrap_edge() {
+        let particles = [
+            particle(0, 1.0, 50.0),
+            particle(1, 99.0, 50.0),
+            particle(2, 50.0, 50.0),
+            particle(3, 99.0, 99.0),
+            particle(4, 1.0, 1.0),
+        ];
+        let grid = Grid::new(&particles, Vec2::new(100.0, 100.0), 10.0);
+
+        // Across the left and right edges
+        assert_eq!(neighbours(&grid, Vec2::new(1.0, 50.0)), [0, 1]);
+        assert_eq!(neighbours(&grid, Vec2::new(99.0, 50.0)), [0, 1]);
+        // Across a corner
+        assert_eq!(neighbours(&grid, Vec2::new(1.0, 1.0)), [3, 4]);
+        assert_eq!(neighbours(&grid, Vec2::new(99.0, 99.0)), [3, 4]);
+        // Nothing wraps into the middle
+        assert_eq!(neighbours(&grid, Vec2::new(50.0, 50.0)), [2]);
+    }
+
+    #[test]
+    fn grid_visits_each_particle_once_in_a_small_world() {
+        // Fewer than three cells along each axis, so the neighbouring cells
+        // overlap.
+        let particles = [particle(0, 1.0, 1.0), particle(1, 15.0, 5.0)];
+        let grid = Grid::new(&particles, Vec2::new(20.0, 10.0), 10.0);
+
+        assert_eq!(neighbours(&grid, Vec2::new(1.0, 1.0)), [0, 1]);
+    }
+
+    /// One step of the shader's algorithm, testing every pair of particles.
+    fn brute_force_step(world: &mut CpuWorld, rules: &Rules) -> Vec<Particle> {
+        let settings = *world.settings();
+        let world_size = Vec2::from(settings.world_size);
+        let particles = world.particles();
+
+        particles
+            .iter()
+            .enumerate()
+            .map(|(index, particle)| {
+                let mut force = Vec2::zero();
+                let mut neighbours = 0;
+
+                for (other_index, other) in particles.iter().enumerate() {
+                    if other_index == index {
+                        continue;
+                    }
+
+                    let rule = rules.get_rule(particle.kind, other.kind);
+                    let direction = wrapped_delta(particle.position, other.position, world_size);
+                    let distance = direction.magnitude();
+
+                    if distance > 0.0 {
+                        force += direction / distance * rule.force_at(distance);
+                    }
+                    if distance < rule.max_distance {
+                        neighbours += 1;
+                    }
+                }
+
+                let velocity =
+                    (particle.velocity + force * settings.force_multiplier) * settings.friction;
+                let mut position = particle.position + velocity;
+                position.x = position.x.rem_euclid(world_size.x);
+                position.y = position.y.rem_euclid(world_size.y);
+
+                Particle {
+                    position,
+                    velocity,
+                    neighbours,
+                    ..*particle
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn step_matches_every_pair() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let rules = Rules::from_fn(|_, _| {
+            let min_distance = rng.random_range(2.0..10.0);
+            Rule {
+                force: rng.random_range(-1.0..1.0),
+                min_distance,
+                max_distance: rng.random_range(min_distance..30.0),
+            }
+        });
+
+        // Large enough for several grid cells along each axis, so that
+        // particles interact within cells, between them and across the edges.
+        let mut world = CpuWorld::from_seed(300, Vec2::new(160.0, 120.0), ParticleKind::MAX, 3);
+
+        for _ in 0..5 {
+            let expected = brute_force_step(&mut world, &rules);
+            world.step(&rules);
+
+            for (particle, expected) in world.particles().iter().zip(&expected) {
+                let error = wrapped_delta(particle.position, expected.position, world.world_size());
+                // The forces are summed in a different order, so allow for
+                // rounding.
+                assert!(
+                    error.magnitude() < 1e-3,
+                    "particle {} is off by {error}",
+                    particle.id
+                );
+                assert!((particle.velocity - expected.velocity).magnitude() < 1e-3);
+                assert_eq!(particle.neighbours, expected.neighbours);
+                assert!(particle.kind == expected.kind);
+            }
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use vek::Vec2;
+
+use super::Particle;
+
+/// Size of the cells used to measure density. Roughly the scale of the
+/// smallest structures that rules tend to produce.
+const DENSITY_CELL_SIZE: f32 = 20.0;
+
+/// A cell counts as part of a cluster if it holds at least this many times
+/// the average number of particles per cell.
+const CLUSTER_DENSITY_FACTOR: f32 = 3.0;
+
+/// Groups of dense cells holding fewer particles than this are ignored when
+/// counting clusters.
+const MIN_CLUSTER_PARTICLES: u32 = 10;
+
+/// Particle counts binned into a coarse grid covering the world.
+pub struct DensityGrid {
+    width: usize,
+    height: usize,
+    counts: Vec<u32>,
+}
+
+impl DensityGrid {
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss
+    )]
+    #[must_use]
+    pub fn new(particles: &[Particle], world_size: Vec2<f32>) -> Self {
+        let width = ((world_size.x / DENSITY_CELL_SIZE) as usize).max(1);
+        let height = ((world_size.y / DENSITY_CELL_SIZE) as usize).max(1);
+
+        let mut counts = vec![0; width * height];
+        for particle in particles {
+            let x = (particle.position.x / world_size.x * width as f32) as usize;
+            let y = (particle.position.y / world_size.y * height as f32) as usize;
+            counts[y.min(height - 1) * width + x.min(width - 1)] += 1;
+        }
+
+        DensityGrid {
+            width,
+            height,
+            counts,
+        }
+    }
+
+    /// Number of connected groups of dense cells, wrapping around the edges of
+    /// the world, that hold a reasonable number of particles.
+    #[allow(clippy::cast_precision_loss)]
+    #[must_use]
+    pub fn count_clusters(&self) -> usize {
+        let total: u32 = self.counts.iter().sum();
+        let mean = total as f32 / self.counts.len() as f32;
+        let threshold = (mean * CLUSTER_DENSITY_FACTOR).max(2.0);
+
+        let dense: Vec<bool> = self.counts.iter().map(|c| *c as f32 >= threshold).collect();
+        let mut visited = vec![false; self.counts.len()];
+        let mut clusters = 0;
+        let mut stack = Vec::new();
+
+        for start in 0..self.counts.len() {
+            if !dense[start] || visited[start] {
+                continue;
+            }
+
+            let mut particles = 0;
+            visited[start] = true;
+            stack.push(start);
+
+            while let Some(cell) = stack.pop() {
+                particles += self.counts[cell];
+
+                for neighbour in self.neighbours(cell) {
+                    if dense[neighbour] && !visited[neighbour] {
+                        visited[neighbour] = true;
+                        stack.push(neighbour);
+                    }
+                }
+            }
+
+            if particles >= MIN_CLUSTER_PARTICLES {
+                clusters += 1;
+            }
+        }
+
+        clusters
+    }
+
+    /// How similar two grids are, from 0 (nothing in common) to 1 (identical).
+    /// Worlds whose structures persist over time score highly.
+    #[allow(clippy::cast_precision_loss)]
+    #[must_use]
+    pub fn similarity(&self, other: &DensityGrid) -> f32 {
+        let (shared, combined) = self
+            .counts
+            .iter()
+            .zip(&other.counts)
+            .fold((0, 0), |(shared, combined), (a, b)| {
+                (shared + a.min(b), combined + a.max(b))
+            });
+
+        if combined == 0 {
+            1.0
+        } else {
+            shared as f32 / combined as f32
+        }
+    }
+
+    /// Variance of the cell counts divided by their mean. Particles scattered
+    /// uniformly at random give about 1; clumping pushes it higher.
+    #[allow(clippy::cast_precision_loss)]
+    #[must_use]
+    pub fn dispersion(&self) -> f32 {
+        let n = self.counts.len() as f32;
+        let mean = self.counts.iter().sum::<u32>() as f32 / n;
+        if mean == 0.0 {
+            return 0.0;
+        }
+
+        let variance = self
+            .counts
+            .iter()
+            .map(|c| (*c as f32 - mean).powi(2))
+            .sum::<f32>()
+            / n;
+        variance / mean
+    }
+
+    fn neighbours(&self, cell: usize) -> [usize; 4] {
+        let (x, y) = (cell % self.width, cell / self.width);
+        let left = (x + self.width - 1) % self.width;
+        let right = (x + 1) % self.width;
+        let up = (y + self.height - 1) % self.height;
+        let down = (y + 1) % self.height;
+
+        [
+            y * self.width + left,
+            y * self.width + right,
+            up * self.width + x,
+            down * self.width + x,
+        ]
+    }
+}
+
+/// Mean distance moved by each particle per step.
+#[allow(clippy::cast_precision_loss)]
+#[must_use]
+pub fn mean_speed(particles: &[Particle]) -> f32 {
+    if particles.is_empty() {
+        return 0.0;
+    }
+
+    let total: f32 = particles.iter().map(|p| p.velocity.magnitude()).sum();
+    total / particles.len() as f32
+}
+
+#[must_use]
+pub fn all_finite(particles: &[Particle]) -> bool {
+    particles.iter().all(|p| {
+        p.position.map(f32::is_finite).reduce_and() && p.velocity.map(f32::is_finite).reduce_and()
+    })
+}
@@ -0,0 +1,565 @@
+use std::ops::Range;
+
+use rand::{rng, Rng};
+use rand_distr::{Distribution as _, Normal};
+
+use super::{ParticleKind, Rule, Rules};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Symmetry {
+    /// Every rule is drawn independently.
+    None,
+    /// The rule for a→b is the same as the rule for b→a.
+    Symmetric,
+    /// b→a has the opposite force to a→b, so one kind chases the other.
+    Antisymmetric,
+}
+
+impl Symmetry {
+    pub const ALL: [Symmetry; 3] = [Symmetry::None, Symmetry::Symmetric, Symmetry::Antisymmetric];
+
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Symmetry::None => "None",
+            Symmetry::Symmetric => "Symmetric",
+            Symmetry::Antisymmetric => "Antisymmetric",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum DistributionKind {
+    Uniform,
+    Normal,
+    Discrete,
+    LogUniform,
+}
+
+impl DistributionKind {
+    pub const ALL: [DistributionKind; 4] = [
+        DistributionKind::Uniform,
+        DistributionKind::Normal,
+        DistributionKind::Discrete,
+        DistributionKind::LogUniform,
+    ];
+
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            DistributionKind::Uniform => "Uniform",
+            DistributionKind::Normal => "Normal",
+            DistributionKind::Discrete => "Discrete",
+            DistributionKind::LogUniform => "Log-uniform",
+        }
+    }
+}
+
+/// How a single rule parameter is sampled.
+#[derive(Clone)]
+pub enum Distribution {
+    Uniform(Range<f32>),
+    /// Clipped to two standard deviations either side of the mean, so that a
+    /// rare sample can't produce a wildly different rule.
+    Normal {
+        mean: f32,
+        std_dev: f32,
+    },
+    /// Picks one of the values with equal probability.
+    Discrete(Vec<f32>),
+    /// Uniform in log space, so small values are as likely as large ones.
+    /// Useful for distances.
+    LogUniform(Range<f32>),
+}
+
+impl Distribution {
+    /// Smallest start value allowed for `LogUniform`.
+    pub const MIN_LOG_VALUE: f32 = 0.001;
+
+    #[must_use]
+    pub fn kind(&self) -> DistributionKind {
+        match self {
+            Distribution::Uniform(_) => DistributionKind::Uniform,
+            Distribution::Normal { .. } => DistributionKind::Normal,
+            Distribution::Discrete(_) => DistributionKind::Discrete,
+            Distribution::LogUniform(_) => DistributionKind::LogUniform,
+        }
+    }
+
+    pub fn sample(&self, rng: &mut impl Rng) -> f32 {
+        match self {
+            Distribution::Uniform(range) => sample_range(rng, range.clone()),
+            Distribution::Normal { mean, std_dev } => {
+                let bounds = self.bounds();
+                Normal::new(*mean, std_dev.max(0.0))
+                    .map_or(*mean, |normal| normal.sample(rng))
+                    .clamp(bounds.start, bounds.end)
+            }
+            Distribution::Discrete(values) => {
+                if values.is_empty() {
+                    0.0
+                } else {
+                    values[rng.random_range(0..values.len())]
+                }
+            }
+            Distribution::LogUniform(range) => {
+                let start = range.start.max(Self::MIN_LOG_VALUE).ln();
+                let end = range.end.max(Self::MIN_LOG_VALUE).ln();
+                sample_range(rng, start..end).exp()
+            }
+        }
+    }
+
+    /// The range that samples fall in, used when switching between kinds.
+    fn bounds(&self) -> Range<f32> {
+        match self {
+            Distribution::Uniform(range) | Distribution::LogUniform(range) => range.clone(),
+            Distribution::Normal { mean, std_dev } => {
+                let std_dev = std_dev.max(0.0);
+                (mean - 2.0 * std_dev)..(mean + 2.0 * std_dev)
+            }
+            Distribution::Discrete(values) => {
+                let min = values.iter().copied().fold(f32::INFINITY, f32::min);
+                let max = values.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+                if min <= max {
+                    min..max
+                } else {
+                    0.0..0.0
+                }
+            }
+        }
+    }
+
+    /// The same distribution with every value multiplied by `factor`. Ranges
+    /// stay in ascending order when `factor` is negative.
+    #[must_use]
+    pub fn scaled(&self, factor: f32) -> Distribution {
+        let scale_range = |range: &Range<f32>| {
+            let (start, end) = (range.start * factor, range.end * factor);
+            start.min(end)..start.max(end)
+        };
+
+        match self {
+            Distribution::Uniform(range) => Distribution::Uniform(scale_range(range)),
+            Distribution::Normal { mean, std_dev } => Distribution::Normal {
+                mean: mean * factor,
+                std_dev: std_dev * factor.abs(),
+            },
+            Distribution::Discrete(values) => {
+                Distribution::Discrete(values.iter().map(|v| v * factor).collect())
+            }
+            Distribution::LogUniform(range) => Distribution::LogUniform(scale_range(range)),
+        }
+    }
+
+    /// Converts to a distribution of a different kind that covers roughly the
+    /// same values.
+    #[must_use]
+    pub fn to_kind(&self, kind: DistributionKind) -> Distribution {
+        let bounds = self.bounds();
+        match kind {
+            DistributionKind::Uniform => Distribution::Uniform(bounds),
+            DistributionKind::Normal => Distribution::Normal {
+                mean: bounds.start + (bounds.end - bounds.start) / 2.0,
+                std_dev: (bounds.end - bounds.start) / 4.0,
+            },
+            DistributionKind::Discrete => Distribution::Discrete(vec![bounds.start, bounds.end]),
+            DistributionKind::LogUniform => Distribution::LogUniform(
+                bounds.start.max(Self::MIN_LOG_VALUE)..bounds.end.max(Self::MIN_LOG_VALUE),
+            ),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RuleGenerationParameters {
+    pub min_distance: Distribution,
+    /// Sampled separately and added to `min_distance`.
+    pub max_distance: Distribution,
+    /// Magnitude of the force; the sign is chosen using
+    /// `attraction_probability`.
+    pub force: Distribution,
+    /// Probability that a rule attracts, rather than repels.
+    pub attraction_probability: f32,
+
+    pub symmetry: Symmetry,
+    /// Probability that a pair of different kinds doesn't interact at all.
+    pub sparsity: f32,
+    /// Probability that a kind attracts, rather than repels, its own kind.
+    pub self_attraction: f32,
+    /// Arrange the kinds in a cycle where each kind chases the next one and
+    /// flees the previous one.
+    pub predator_prey_cycle: bool,
+    /// Number of kinds in use. The predator-prey cycle closes after the last
+    /// of these, so that it chases the first. With only two kinds the cycle
+    /// would have each chase and flee the other, so the first chases the
+    /// second instead.
+    pub species: u32,
+}
+
+impl Default for RuleGenerationParameters {
+    fn default() -> Self {
+        Self {
+            min_distance: Distribution::Uniform(30.0_f32..50.0_f32),
+            max_distance: Distribution::Uniform(70.0_f32..250.0_f32),
+            force: Distribution::Uniform(0.3_f32..1.0_f32),
+            attraction_probability: 0.5,
+
+            symmetry: Symmetry::None,
+            sparsity: 0.0,
+            self_attraction: 0.5,
+            predator_prey_cycle: false,
+            species: ParticleKind::MAX,
+        }
+    }
+}
+
+impl Rules {
+    #[must_use]
+    pub fn new_random(params: &RuleGenerationParameters) -> Self {
+        Self::new_random_with_rng(params, &mut rng())
+    }
+
+    /// Like `new_random`, but drawing from `rng` so that the same seed always
+    /// gives the same rules.
+    pub fn new_random_with_rng(params: &RuleGenerationParameters, rng: &mut impl Rng) -> Self {
+        let mut rules = Rules::from_fn(|_, _| Rule::new_random(params, rng));
+
+        for a in 0..ParticleKind::MAX {
+            rules.constrain_self_rule(ParticleKind(a), params, rng);
+
+            for b in (a + 1)..ParticleKind::MAX {
+                rules.constrain_pair(ParticleKind(a), ParticleKind(b), params, rng);
+            }
+        }
+
+        rules
+    }
+
+    /// Gives kind `a`'s rule for its own kind the sign chosen by
+    /// `self_attraction`.
+    fn constrain_self_rule(
+        &mut self,
+        a: ParticleKind,
+        params: &RuleGenerationParameters,
+        rng: &mut impl Rng,
+    ) {
+        let self_rule = self.get_rule_mut(a, a);
+        self_rule.force = self_rule.force.abs()
+            * sign(rng.random_bool(f64::from(params.self_attraction.clamp(0.0, 1.0))));
+    }
+
+    /// Makes b→a follow a→b according to the symmetry, and then applies the
+    /// predator-prey cycle or sparsity to the pair.
+    fn constrain_pair(
+        &mut self,
+        a: ParticleKind,
+        b: ParticleKind,
+        params: &RuleGenerationParameters,
+        rng: &mut impl Rng,
+    ) {
+        let rule = *self.get_rule(a, b);
+
+        match params.symmetry {
+            Symmetry::None => (),
+            Symmetry::Symmetric => *self.get_rule_mut(b, a) = rule,
+            Symmetry::Antisymmetric => {
+                *self.get_rule_mut(b, a) = Rule {
+                    force: -rule.force,
+                    ..rule
+                };
+            }
+        }
+
+        if params.predator_prey_cycle && is_cycle_neighbour(a, b, params.species) {
+            // a and b are adjacent in the cycle; the earlier one chases the
+            // later one, which runs away. With two species they are adjacent
+            // both ways round, and since a < b here a is the predator.
+            let (predator, prey) = if (a.0 + 1) % params.species == b.0 {
+                (a, b)
+            } else {
+                (b, a)
+            };
+            let chase = self.get_rule_mut(predator, prey);
+            chase.force = chase.force.abs();
+            let flee = self.get_rule_mut(prey, predator);
+            flee.force = -flee.force.abs();
+        } else if rng.random_bool(f64::from(params.sparsity.clamp(0.0, 1.0))) {
+            self.get_rule_mut(a, b).force = 0.0;
+            self.get_rule_mut(b, a).force = 0.0;
+        }
+    }
+
+    /// Draws a new rule for how kind `a` reacts to kind `b`, following the
+    /// same symmetry, sparsity, self-attraction and cycle settings as
+    /// `new_random`. Those can change b→a as well.
+    pub fn randomize_rule(
+        &mut self,
+        a: ParticleKind,
+        b: ParticleKind,
+        params: &RuleGenerationParameters,
+    ) {
+        let mut rng = rng();
+        *self.get_rule_mut(a, b) = Rule::new_random(params, &mut rng);
+
+        if a == b {
+            self.constrain_self_rule(a, params, &mut rng);
+        } else {
+            self.constrain_pair(a, b, params, &mut rng);
+        }
+    }
+
+    /// Randomizes the rules for how kind `a` reacts to every other kind.
+    pub fn randomize_row(&mut self, a: ParticleKind, params: &RuleGenerationParameters) {
+        for b in ParticleKind::all() {
+            self.randomize_rule(a, b, params);
+        }
+    }
+
+    /// Randomizes the rules for how every kind reacts to kind `b`.
+    pub fn randomize_column(&mut self, b: ParticleKind, params: &RuleGenerationParameters) {
+        for a in ParticleKind::all() {
+            self.randomize_rule(a, b, params);
+        }
+    }
+}
+
+impl Rule {
+    pub(super) fn new_random(params: &RuleGenerationParameters, rng: &mut impl Rng) -> Self {
+        // The shader divides by both distances, so keep them away from zero.
+        let min_distance = params.min_distance.sample(rng).max(0.001);
+        let max_distance = min_distance + params.max_distance.sample(rng).max(0.0);
+
+        let attract = rng.random_bool(f64::from(params.attraction_probability.clamp(0.0, 1.0)));
+
+        Rule {
+            force: params.force.sample(rng).abs() * sign(attract),
+            min_distance,
+            max_distance,
+        }
+    }
+}
+
+fn sample_range(rng: &mut impl Rng, range: Range<f32>) -> f32 {
+    if range.start < range.end {
+        rng.random_range(range)
+    } else {
+        range.start
+    }
+}
+
+fn sign(positive: bool) -> f32 {
+    if positive {
+        1.0
+    } else {
+        -1.0
+    }
+}
+
+/// Whether `a` and `b` are next to each other in a cycle of the first
+/// `species` kinds.
+fn is_cycle_neighbour(a: ParticleKind, b: ParticleKind, species: u32) -> bool {
+    a.0 < species && b.0 < species && ((a.0 + 1) % species == b.0 || (b.0 + 1) % species == a.0)
+}
+
+#[cfg(test)]
+// Rules are copied or negated, and distributions scaled by exactly
+// representable factors, so exact comparisons are intended.
+#[allow(clippy::float_cmp)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::{Distribution, RuleGenerationParameters, Symmetry};
+    use crate::particle_life::{ParticleKind, Rules};
+
+    fn rules(params: &RuleGenerationParameters) -> Rules {
+        Rules::new_random_with_rng(params, &mut StdRng::seed_from_u64(1))
+    }
+
+    /// Every pair of different kinds, each once.
+    fn pairs() -> impl Iterator<Item = (ParticleKind, ParticleKind)> {
+        ParticleKind::all()
+            .flat_map(|a| ParticleKind::all().map(move |b| (a, b)))
+            .filter(|(a, b)| a.index() < b.index())
+    }
+
+    fn force(rules: &Rules, a: u32, b: u32) -> f32 {
+        rules.get_rule(ParticleKind(a), ParticleKind(b)).force
+    }
+
+    fn assert_samples_within_bounds(distribution: &Distribution) {
+        let bounds = distribution.bounds();
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..10_000 {
+            let value = distribution.sample(&mut rng);
+            assert!(
+                bounds.start <= value && value <= bounds.end,
+                "{value} is outside {bounds:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn samples_stay_within_bounds() {
+        assert_samples_within_bounds(&Distribution::Uniform(30.0..50.0));
+        assert_samples_within_bounds(&Distribution::Normal {
+            mean: 0.5,
+            std_dev: 0.2,
+        });
+        // Two values far apart
+        assert_samples_within_bounds(&Distribution::Discrete(vec![-1.0, 1.0]));
+        assert_samples_within_bounds(&Distribution::LogUniform(1.0..250.0));
+    }
+
+    #[test]
+    fn samples_cover_both_modes() {
+        let distribution = Distribution::Discrete(vec![-1.0, 1.0]);
+        let mut rng = StdRng::seed_from_u64(1);
+        let samples: Vec<f32> = (0..100).map(|_| distribution.sample(&mut rng)).collect();
+
+        assert!(samples.contains(&-1.0));
+        assert!(samples.contains(&1.0));
+        assert!(samples.iter().all(|v| *v == -1.0 || *v == 1.0));
+    }
+
+    #[test]
+    fn scaling_by_a_negative_factor_keeps_ranges_ascending() {
+        let Distribution::Uniform(range) = Distribution::Uniform(1.0..4.0).scaled(-2.0) else {
+            panic!("scaling changed the kind");
+        };
+        assert_eq!(range, -8.0..-2.0);
+        assert_samples_within_bounds(&Distribution::Uniform(range));
+
+        let Distribution::LogUniform(range) = Distribution::LogUniform(1.0..4.0).scaled(-0.5)
+        else {
+            panic!("scaling changed the kind");
+        };
+        assert_eq!(range, -2.0..-0.5);
+
+        let Distribution::Normal { mean, std_dev } = (Distribution::Normal {
+            mean: 1.0,
+            std_dev: 0.5,
+        })
+        .scaled(-2.0) else {
+            panic!("scaling changed the kind");
+        };
+        assert_eq!((mean, std_dev), (-2.0, 1.0));
+    }
+
+    #[test]
+    fn scaling_by_a_positive_factor() {
+        let Distribution::Uniform(range) = Distribution::Uniform(1.0..4.0).scaled(2.0) else {
+            panic!("scaling changed the kind");
+        };
+        assert_eq!(range, 2.0..8.0);
+
+        let Distribution::Discrete(values) = Distribution::Discrete(vec![1.0, -3.0]).scaled(0.5)
+        else {
+            panic!("scaling changed the kind");
+        };
+        assert_eq!(values, [0.5, -1.5]);
+    }
+
+    #[test]
+    fn symmetric_rules_match_both_ways() {
+        let rules = rules(&RuleGenerationParameters {
+            symmetry: Symmetry::Symmetric,
+            ..Default::default()
+        });
+
+        for (a, b) in pairs() {
+            let (ab, ba) = (rules.get_rule(a, b), rules.get_rule(b, a));
+            assert_eq!(ab.force, ba.force);
+            assert_eq!(ab.min_distance, ba.min_distance);
+            assert_eq!(ab.max_distance, ba.max_distance);
+        }
+    }
+
+    #[test]
+    fn antisymmetric_rules_have_opposite_forces() {
+        let rules = rules(&RuleGenerationParameters {
+            symmetry: Symmetry::Antisymmetric,
+            ..Default::default()
+        });
+
+        for (a, b) in pairs() {
+            let (ab, ba) = (rules.get_rule(a, b), rules.get_rule(b, a));
+            assert_ne!(ab.force, 0.0);
+            assert_eq!(ab.force, -ba.force);
+            assert_eq!(ab.min_distance, ba.min_distance);
+            assert_eq!(ab.max_distance, ba.max_distance);
+        }
+    }
+
+    #[test]
+    fn sparsity_removes_interactions_between_kinds() {
+        let sparse = rules(&RuleGenerationParameters {
+            sparsity: 1.0,
+            ..Default::default()
+        });
+        let dense = rules(&RuleGenerationParameters {
+            sparsity: 0.0,
+            ..Default::default()
+        });
+
+        for (a, b) in pairs() {
+            assert_eq!(sparse.get_rule(a, b).force, 0.0);
+            assert_eq!(sparse.get_rule(b, a).force, 0.0);
+            assert_ne!(dense.get_rule(a, b).force, 0.0);
+        }
+        // Kinds still react to their own kind
+        for a in ParticleKind::all() {
+            assert_ne!(sparse.get_rule(a, a).force, 0.0);
+        }
+    }
+
+    #[test]
+    fn self_attraction_sets_the_sign_of_the_diagonal() {
+        for (self_attraction, attracts) in [(1.0, true), (0.0, false)] {
+            let rules = rules(&RuleGenerationParameters {
+                self_attraction,
+                ..Default::default()
+            });
+            for a in ParticleKind::all() {
+                let force = rules.get_rule(a, a).force;
+                assert_eq!(force > 0.0, attracts, "{self_attraction}: {force}");
+            }
+        }
+    }
+
+    #[test]
+    fn predator_prey_cycle() {
+        let rules = rules(&RuleGenerationParameters {
+            predator_prey_cycle: true,
+            species: 4,
+            sparsity: 1.0,
+            ..Default::default()
+        });
+
+        // Each kind chases the next, and the last chases the first
+        for (predator, prey) in [(0, 1), (1, 2), (2, 3), (3, 0)] {
+            assert!(force(&rules, predator, prey) > 0.0);
+            assert!(force(&rules, prey, predator) < 0.0);
+        }
+        // Kinds that aren't next to each other are left to sparsity
+        assert_eq!(force(&rules, 0, 2), 0.0);
+        assert_eq!(force(&rules, 1, 3), 0.0);
+        assert_eq!(force(&rules, 0, 4), 0.0);
+    }
+
+    #[test]
+    fn predator_prey_cycle_with_two_species() {
+        for seed in 0..10 {
+            let params = RuleGenerationParameters {
+                predator_prey_cycle: true,
+                species: 2,
+                ..Default::default()
+            };
+            let rules = Rules::new_random_with_rng(&params, &mut StdRng::seed_from_u64(seed));
+
+            // The first kind chases the second
+            assert!(force(&rules, 0, 1) > 0.0);
+            assert!(force(&rules, 1, 0) < 0.0);
+        }
+    }
+}
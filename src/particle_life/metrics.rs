@@ -0,0 +1,298 @@
+use std::{collections::VecDeque, f32::consts::TAU, fmt::Write as _};
+
+use vek::Vec2;
+
+use super::{cpu::wrapped_delta, fitness::DensityGrid, Particle, ParticleKind};
+
+/// Particles moving slower than this per step count as stationary.
+const STATIONARY_SPEED: f32 = 0.05;
+
+const NUM_KINDS: usize = ParticleKind::MAX as usize;
+
+/// Summary statistics of the particles at one step, to show whether a world is
+/// settling, oscillating or exploding.
+#[derive(Clone, Copy, Default)]
+pub struct Metrics {
+    pub mean_speed: f32,
+    pub max_speed: f32,
+    /// Total kinetic energy, treating every particle as having unit mass.
+    pub kinetic_energy: f32,
+    /// Root mean square distance of each kind of particle from the centre of
+    /// its kind, or 0 for kinds with no particles. Small values mean a kind
+    /// has gathered in one place.
+    pub species_spread: [f32; NUM_KINDS],
+    /// Mean number of other particles within range of each particle's rules.
+    pub mean_neighbours: f32,
+    /// Fraction of the particles that are stationary.
+    pub stationary_fraction: f32,
+    /// How clumped the particles are: about 1 when they are spread out
+    /// evenly, higher when they gather into groups.
+    pub dispersion: f32,
+}
+
+impl Metrics {
+    #[allow(clippy::cast_precision_loss)]
+    #[must_use]
+    pub fn measure(particles: &[Particle], world_size: Vec2<f32>) -> Self {
+        if particles.is_empty() {
+            return Metrics::default();
+        }
+
+        let n = particles.len() as f32;
+        let mut metrics = Metrics::default();
+        let mut total_speed = 0.0;
+        let mut total_neighbours = 0.0;
+        let mut stationary = 0;
+
+        for particle in particles {
+            let speed = particle.velocity.magnitude();
+            total_speed += speed;
+            metrics.max_speed = metrics.max_speed.max(speed);
+            metrics.kinetic_energy += 0.5 * particle.velocity.magnitude_squared();
+            total_neighbours += particle.neighbours as f32;
+            if speed < STATIONARY_SPEED {
+                stationary += 1;
+            }
+        }
+
+        metrics.mean_speed = total_speed / n;
+        metrics.mean_neighbours = total_neighbours / n;
+        metrics.stationary_fraction = stationary as f32 / n;
+        metrics.species_spread = species_spread(particles, world_size);
+        metrics.dispersion = DensityGrid::new(particles, world_size).dispersion();
+
+        metrics
+    }
+
+    /// The average of each metric over `samples`.
+    #[allow(clippy::cast_precision_loss)]
+    #[must_use]
+    pub fn mean(samples: &[Metrics]) -> Metrics {
+        if samples.is_empty() {
+            return Metrics::default();
+        }
+
+        let n = samples.len() as f32;
+        let mean = |f: fn(&Metrics) -> f32| samples.iter().map(f).sum::<f32>() / n;
+
+        Metrics {
+            mean_speed: mean(|m| m.mean_speed),
+            max_speed: mean(|m| m.max_speed),
+            kinetic_energy: mean(|m| m.kinetic_energy),
+            species_spread: std::array::from_fn(|kind| {
+                samples.iter().map(|m| m.species_spread[kind]).sum::<f32>() / n
+            }),
+            mean_neighbours: mean(|m| m.mean_neighbours),
+            stationary_fraction: mean(|m| m.stationary_fraction),
+            dispersion: mean(|m| m.dispersion),
+        }
+    }
+
+    /// The metric with the given name, using the same names as the CSV
+    /// columns.
+    #[must_use]
+    pub fn value(&self, name: &str) -> Option<f32> {
+        match name {
+            "mean_speed" => Some(self.mean_speed),
+            "max_speed" => Some(self.max_speed),
+            "kinetic_energy" => Some(self.kinetic_energy),
+            "mean_neighbours" => Some(self.mean_neighbours),
+            "stationary_fraction" => Some(self.stationary_fraction),
+            "dispersion" => Some(self.dispersion),
+            _ => name
+                .strip_prefix("spread_")
+                .and_then(|kind| kind.parse::<usize>().ok())
+                .and_then(|kind| self.species_spread.get(kind).copied()),
+        }
+    }
+
+    /// Column names matching `csv_row`.
+    #[must_use]
+    pub fn csv_header() -> String {
+        let mut header = String::from(
+            "mean_speed,max_speed,kinetic_energy,mean_neighbours,stationary_fraction,dispersion",
+        );
+        for kind in ParticleKind::all() {
+            write!(header, ",spread_{}", kind.index()).unwrap();
+        }
+        header
+    }
+
+    /// The metrics as comma separated values, without a trailing newline.
+    #[must_use]
+    pub fn csv_row(&self) -> String {
+        let mut row = format!(
+            "{},{},{},{},{},{}",
+            self.mean_speed,
+            self.max_speed,
+            self.kinetic_energy,
+            self.mean_neighbours,
+            self.stationary_fraction,
+            self.dispersion
+        );
+        for spread in self.species_spread {
+            write!(row, ",{spread}").unwrap();
+        }
+        row
+    }
+}
+
+/// The centre of each kind is found with a circular mean along each axis, so
+/// that a group straddling the edge of the world isn't split in two.
+#[allow(clippy::cast_precision_loss)]
+fn species_spread(particles: &[Particle], world_size: Vec2<f32>) -> [f32; NUM_KINDS] {
+    let mut sums = [(Vec2::<f32>::zero(), Vec2::<f32>::zero()); NUM_KINDS];
+    let mut counts = [0_usize; NUM_KINDS];
+
+    for particle in particles {
+        let angle = particle.position / world_size * TAU;
+        let (cos, sin) = &mut sums[particle.kind.index()];
+        *cos += angle.map(f32::cos);
+        *sin += angle.map(f32::sin);
+        counts[particle.kind.index()] += 1;
+    }
+
+    let centres: [Vec2<f32>; NUM_KINDS] = std::array::from_fn(|kind| {
+        let (cos, sin) = sums[kind];
+        let angle = Vec2::new(sin.x.atan2(cos.x), sin.y.atan2(cos.y));
+        (angle / TAU).map(|a| a.rem_euclid(1.0)) * world_size
+    });
+
+    let mut squared = [0.0_f32; NUM_KINDS];
+    for particle in particles {
+        let kind = particle.kind.index();
+        squared[kind] +=
+            wrapped_delta(centres[kind], particle.position, world_size).magnitude_squared();
+    }
+
+    std::array::from_fn(|kind| {
+        if counts[kind] == 0 {
+            0.0
+        } else {
+            (squared[kind] / counts[kind] as f32).sqrt()
+        }
+    })
+}
+
+/// Number of samples kept by default; about ten seconds at 60 frames a second.
+const DEFAULT_HISTORY_LENGTH: usize = 600;
+
+/// The most recent metrics, oldest first, for plotting.
+pub struct MetricsHistory {
+    samples: VecDeque<Metrics>,
+    capacity: usize,
+}
+
+impl MetricsHistory {
+    #[must_use]
+    pub fn new(capacity: usize) -> Self {
+        MetricsHistory {
+            samples: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn push(&mut self, metrics: Metrics) {
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(metrics);
+    }
+
+    /// Forgets every sample, for when the world has changed too much for them
+    /// to say anything about it.
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+
+    #[must_use]
+    pub fn latest(&self) -> Option<&Metrics> {
+        self.samples.back()
+    }
+
+    /// Up to `count` of the most recent samples, oldest first.
+    #[must_use]
+    pub fn recent(&self, count: usize) -> Vec<Metrics> {
+        let skip = self.samples.len().saturating_sub(count);
+        self.samples.iter().skip(skip).copied().collect()
+    }
+
+    /// One value from each sample, oldest first.
+    pub fn series(&self, f: impl Fn(&Metrics) -> f32) -> Vec<f32> {
+        self.samples.iter().map(f).collect()
+    }
+}
+
+impl Default for MetricsHistory {
+    fn default() -> Self {
+        Self::new(DEFAULT_HISTORY_LENGTH)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use vek::Vec2;
+
+    use super::Metrics;
+    use crate::particle_life::{Particle, ParticleKind};
+
+    /// Four by two density cells of 20 units each.
+    const WORLD_SIZE: Vec2<f32> = Vec2::new(80.0, 40.0);
+
+    fn particle(
+        position: (f32, f32),
+        velocity: (f32, f32),
+        kind: u32,
+        neighbours: u32,
+    ) -> Particle {
+        Particle {
+            position: Vec2::from(position),
+            velocity: Vec2::from(velocity),
+            kind: ParticleKind(kind),
+            neighbours,
+            id: 0,
+        }
+    }
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-4,
+            "expected {expected}, got {actual}"
+        );
+    }
+
+    #[test]
+    fn measure_matches_hand_computed_values() {
+        let particles = [
+            particle((10.0, 10.0), (3.0, 4.0), 0, 2),
+            particle((30.0, 10.0), (0.0, 0.01), 0, 4),
+            // Kind 1 straddles the left and right edges
+            particle((75.0, 30.0), (0.0, -1.0), 1, 0),
+            // Exactly at the stationary speed, which doesn't count
+            particle((5.0, 30.0), (0.03, 0.04), 1, 6),
+        ];
+
+        let metrics = Metrics::measure(&particles, WORLD_SIZE);
+
+        assert_close(metrics.mean_speed, (5.0 + 0.01 + 1.0 + 0.05) / 4.0);
+        assert_close(metrics.max_speed, 5.0);
+        assert_close(metrics.kinetic_energy, 0.5 * (25.0 + 0.0001 + 1.0 + 0.0025));
+        assert_close(metrics.mean_neighbours, 3.0);
+        assert_close(metrics.stationary_fraction, 0.25);
+
+        // Kind 0 is centred on (20, 10), kind 1 on (0, 30) across the edge
+        assert_close(metrics.species_spread[0], 10.0);
+        assert_close(metrics.species_spread[1], 5.0);
+        assert!(metrics.species_spread[2..].iter().all(|s| *s == 0.0));
+
+        // Four of the eight cells hold one particle: mean 0.5, variance 0.25
+        assert_close(metrics.dispersion, 0.5);
+    }
+
+    #[test]
+    fn measure_of_nothing_is_zero() {
+        let metrics = Metrics::measure(&[], WORLD_SIZE);
+        assert_close(metrics.mean_speed, 0.0);
+        assert_close(metrics.dispersion, 0.0);
+    }
+}
@@ -1,36 +1,33 @@
 use array_init::array_init;
-use d3dx12::{HeapProperties, Mappable, ResourceDesc, ShaderBytecode};
 use palette::{FromColor, Hsl, Srgb};
-use rand::{rng, Rng};
-use std::{
-    mem::{size_of, size_of_val},
-    ops::Range,
-};
-use vek::Vec2;
-use windows::{
-    core::HSTRING,
-    Win32::Graphics::Direct3D12::{
-        ID3D12Device, ID3D12GraphicsCommandList, ID3D12PipelineState, ID3D12Resource,
-        ID3D12RootSignature, D3D12_COMPUTE_PIPELINE_STATE_DESC, D3D12_HEAP_FLAG_NONE,
-        D3D12_HEAP_TYPE, D3D12_HEAP_TYPE_DEFAULT, D3D12_HEAP_TYPE_UPLOAD,
-        D3D12_RESOURCE_STATE_COMMON,
-    },
-};
-
-use crate::renderer::points::Vertex;
-
-pub struct World {
-    shader_constants: ShaderGlobalConstants,
-    staging_buffers: [ID3D12Resource; 2],
-    reset_particles: bool,
-
-    vertex_buffer: ID3D12Resource,
-    particles_buffers: [ID3D12Resource; 2],
-    constant_buffer: ID3D12Resource,
-
-    rs: ID3D12RootSignature,
-    pso: ID3D12PipelineState,
-}
+use rand::Rng;
+use vek::{Vec2, Vec3};
+
+use crate::raster::Vertex;
+
+pub mod classify;
+pub mod clusters;
+pub mod cpu;
+pub mod fitness;
+mod generation;
+pub mod metrics;
+mod morph;
+mod mutation;
+pub mod presets;
+pub mod rdf;
+pub mod scenario;
+pub mod scene;
+pub mod snapshot;
+pub mod tracking;
+pub mod trajectory;
+#[cfg(windows)]
+mod world;
+
+pub use generation::{Distribution, DistributionKind, RuleGenerationParameters, Symmetry};
+pub use morph::RuleMorph;
+pub use mutation::MutationParameters;
+#[cfg(windows)]
+pub use world::World;
 
 #[repr(C)]
 #[derive(Clone, Copy)]
@@ -40,218 +37,119 @@ pub struct ShaderGlobalConstants {
     pub world_size: [f32; 2],
     pub friction: f32,
     pub force_multiplier: f32,
+    use_color_override: u32,
 }
 
-impl World {
-    pub fn new(device: &ID3D12Device, num_particles: usize, size: Vec2<f32>) -> Self {
-        let shader_constants = ShaderGlobalConstants {
+impl ShaderGlobalConstants {
+    /// Fraction of its velocity a particle keeps from one step to the next.
+    pub const DEFAULT_FRICTION: f32 = 0.9;
+    pub const DEFAULT_FORCE_MULTIPLIER: f32 = 0.05;
+
+    #[must_use]
+    pub fn new(num_particles: usize, size: Vec2<f32>) -> Self {
+        ShaderGlobalConstants {
             particle_type_max: u32::from(ParticleKind::MAX),
             num_particles: u32::try_from(num_particles).unwrap(),
             world_size: size.into_array(),
-            friction: 0.9_f32,
-            force_multiplier: 0.05_f32,
-        };
-
-        let particle_buffer_size = num_particles * size_of::<Particle>();
-        let vertex_buffer_size = num_particles * size_of::<Vertex>();
-
-        let num_rules = (ParticleKind::MAX * ParticleKind::MAX) as usize;
-        let constant_buffer_size =
-            size_of::<ShaderGlobalConstants>() + size_of::<Rule>() * num_rules;
-
-        let rs = create_root_signature(device);
-        let pso = create_pipeline_state(device, &rs);
-
-        World {
-            shader_constants,
-            vertex_buffer: create_buffer(device, vertex_buffer_size, "vertex_buffer"),
-            particles_buffers: array_init(|i| {
-                create_buffer(
-                    device,
-                    particle_buffer_size,
-                    format!("particles-{i}").as_str(),
-                )
-            }),
-            staging_buffers: array_init(|i| {
-                create_upload_buffer(
-                    device,
-                    particle_buffer_size + constant_buffer_size,
-                    format!("staging-{i}").as_str(),
-                )
-            }),
-            constant_buffer: create_buffer(device, constant_buffer_size, "constant_buffer"),
-
-            reset_particles: true,
-
-            rs,
-            pso,
+            friction: Self::DEFAULT_FRICTION,
+            force_multiplier: Self::DEFAULT_FORCE_MULTIPLIER,
+            use_color_override: 0,
         }
     }
-
-    pub fn settings(&mut self) -> &mut ShaderGlobalConstants {
-        &mut self.shader_constants
-    }
-
-    pub fn reset_particles(&mut self) {
-        self.reset_particles = true;
-    }
-
-    pub fn update(&mut self, rules: &Rules, cl: &ID3D12GraphicsCommandList) {
-        self.update_buffers(rules, cl);
-        self.reset_particles = false;
-
-        unsafe {
-            cl.SetComputeRootSignature(&self.rs);
-            cl.SetPipelineState(&self.pso);
-            cl.SetComputeRootConstantBufferView(0, self.constant_buffer.GetGPUVirtualAddress());
-            cl.SetComputeRootShaderResourceView(
-                1,
-                self.constant_buffer.GetGPUVirtualAddress()
-                    + size_of::<ShaderGlobalConstants>() as u64,
-            );
-            cl.SetComputeRootShaderResourceView(
-                2,
-                self.particles_buffers[0].GetGPUVirtualAddress(),
-            );
-            cl.SetComputeRootUnorderedAccessView(
-                3,
-                self.particles_buffers[1].GetGPUVirtualAddress(),
-            );
-            cl.SetComputeRootUnorderedAccessView(4, self.vertex_buffer.GetGPUVirtualAddress());
-            cl.Dispatch(self.shader_constants.num_particles / 32, 1, 1);
-        }
-
-        self.staging_buffers.swap(0, 1);
-        self.particles_buffers.swap(0, 1);
-    }
-
-    fn update_buffers(&mut self, rules: &Rules, cl: &ID3D12GraphicsCommandList) {
-        unsafe {
-            let staging_dest = self.staging_buffers[0].clone();
-            let staging = &mut self.staging_buffers[0];
-            let mut dest = staging.map_raw();
-            let mut dest_offset = 0;
-
-            // Always copy the shader constants and rules
-            *dest.as_mut_offset(dest_offset) = self.shader_constants;
-            cl.CopyBufferRegion(
-                &self.constant_buffer,
-                u64::try_from(dest_offset).unwrap(),
-                &staging_dest,
-                u64::try_from(dest_offset).unwrap(),
-                u64::try_from(size_of_val(&self.shader_constants)).unwrap(),
-            );
-            dest_offset += isize::try_from(size_of_val(&self.shader_constants)).unwrap();
-
-            *dest.as_mut_offset(dest_offset) = *rules;
-            cl.CopyBufferRegion(
-                &self.constant_buffer,
-                u64::try_from(dest_offset).unwrap(),
-                &staging_dest,
-                u64::try_from(dest_offset).unwrap(),
-                size_of_val(rules) as u64,
-            );
-            dest_offset += isize::try_from(size_of_val(rules)).unwrap();
-
-            // Copy a new set of random particles if needed
-            if self.reset_particles {
-                let size = Vec2::from(self.shader_constants.world_size);
-                let num_particles = self.shader_constants.num_particles;
-
-                let particles: Vec<_> = (0..num_particles).map(|_| Particle::new(size)).collect();
-
-                let dest_particles = dest.as_mut_slice_offset(dest_offset, num_particles as usize);
-                dest_particles.copy_from_slice(particles.as_slice());
-
-                cl.CopyBufferRegion(
-                    &self.particles_buffers[0],
-                    0,
-                    &staging_dest,
-                    u64::try_from(dest_offset).unwrap(),
-                    u64::from(num_particles) * size_of::<Particle>() as u64,
-                );
-            }
-        }
-    }
-
-    pub fn get_vertex_buffer(&self) -> (&ID3D12Resource, u32) {
-        (&self.vertex_buffer, self.shader_constants.num_particles)
-    }
-}
-
-fn create_buffer_with_type(
-    device: &ID3D12Device,
-    size: usize,
-    heap_type: D3D12_HEAP_TYPE,
-    name: &str,
-) -> ID3D12Resource {
-    unsafe {
-        let mut resource: Option<ID3D12Resource> = None;
-        device
-            .CreateCommittedResource(
-                &HeapProperties::standard(heap_type),
-                D3D12_HEAP_FLAG_NONE,
-                &ResourceDesc::buffer(size),
-                D3D12_RESOURCE_STATE_COMMON,
-                None,
-                &mut resource,
-            )
-            .unwrap();
-        let resource = resource.unwrap();
-        resource.SetName(&HSTRING::from(name)).unwrap();
-        resource
-    }
-}
-
-fn create_upload_buffer(device: &ID3D12Device, size: usize, name: &str) -> ID3D12Resource {
-    create_buffer_with_type(device, size, D3D12_HEAP_TYPE_UPLOAD, name)
-}
-
-fn create_buffer(device: &ID3D12Device, size: usize, name: &str) -> ID3D12Resource {
-    create_buffer_with_type(device, size, D3D12_HEAP_TYPE_DEFAULT, name)
 }
 
 #[derive(Clone, Copy)]
 #[repr(C)]
-struct Particle {
+pub struct Particle {
     position: Vec2<f32>,
     velocity: Vec2<f32>,
     kind: ParticleKind,
+    /// Number of other particles within range of this one's rules in the last
+    /// step.
+    neighbours: u32,
+    /// Stays with the particle from when it is created until the particles
+    /// are reset.
+    id: u32,
 }
 
 impl Particle {
-    fn new(size: Vec2<f32>) -> Self {
+    /// A particle at rest somewhere in the world, of one of the first `species`
+    /// kinds.
+    fn new(id: u32, size: Vec2<f32>, species: u32, rng: &mut impl Rng) -> Self {
         let x_coordinate_range = 0.0_f32..size.x;
         let y_coordinate_range = 0.0_f32..size.y;
 
-        let mut rng = rng();
-
         Particle {
             position: Vec2::new(
                 rng.random_range(x_coordinate_range.clone()),
                 rng.random_range(y_coordinate_range.clone()),
             ),
             velocity: Vec2::zero(),
-            kind: ParticleKind(rng.random_range(0..ParticleKind::MAX)),
+            kind: ParticleKind(rng.random_range(0..species.clamp(1, ParticleKind::MAX))),
+            neighbours: 0,
+            id,
+        }
+    }
+
+    /// The vertex that the shader writes for this particle: coloured by kind,
+    /// and dimmer the fewer neighbours it had in the last step.
+    #[must_use]
+    pub fn vertex(&self) -> Vertex {
+        #[allow(clippy::cast_precision_loss)]
+        let hue = self.kind.0 as f32 / ParticleKind::MAX as f32;
+        let rgb = Vec3::new(
+            (hue * 6.0 - 3.0).abs() - 1.0,
+            2.0 - (hue * 6.0 - 2.0).abs(),
+            2.0 - (hue * 6.0 - 4.0).abs(),
+        )
+        .map(|c| c.clamp(0.0, 1.0));
+
+        #[allow(clippy::cast_precision_loss)]
+        let hit = (self.neighbours as f32 * 0.01).clamp(0.0, 1.0);
+        let rgb = Vec3::lerp(rgb * 0.1, rgb, hit);
+
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let [r, g, b] = rgb.map(|c| (c * 255.0) as u32).into_array();
+
+        Vertex {
+            position: self.position.into_array(),
+            color: (255 << 24) | (b << 16) | (g << 8) | r,
         }
     }
 }
 
-#[derive(Clone, Copy)]
-#[allow(dead_code)]
-struct ParticleKind(u32);
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct ParticleKind(u32);
 
 #[allow(dead_code)]
 impl ParticleKind {
-    const MAX: u32 = 8;
+    pub const MAX: u32 = 8;
+
+    pub fn all() -> impl Iterator<Item = ParticleKind> {
+        (0..Self::MAX).map(ParticleKind)
+    }
+
+    #[must_use]
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
 
     fn as_color(self) -> u32 {
+        let rgb = Srgb::from_color(self.as_hsl());
+        rgb.into_format().into()
+    }
+
+    #[must_use]
+    pub fn as_rgba(self) -> [f32; 4] {
+        let rgb = Srgb::from_color(self.as_hsl());
+        [rgb.red, rgb.green, rgb.blue, 1.0]
+    }
+
+    fn as_hsl(self) -> Hsl {
         let kind = self.0 as f32;
         let max = Self::MAX as f32;
 
-        let hsl = Hsl::new_srgb(360.0 * (kind / max), 1.0, 0.5);
-        let rgb = Srgb::from_color(hsl);
-        rgb.into_format().into()
+        Hsl::new_srgb(360.0 * (kind / max), 1.0, 0.5)
     }
 }
 
@@ -261,72 +159,142 @@ pub struct Rules {
     rules: [Rule; (ParticleKind::MAX * ParticleKind::MAX) as usize],
 }
 
-#[allow(dead_code)]
 impl Rules {
-    pub fn new_random(params: &RuleGenerationParameters) -> Self {
+    pub fn from_fn(mut f: impl FnMut(ParticleKind, ParticleKind) -> Rule) -> Self {
         Rules {
-            rules: array_init(|_| Rule::new_random(params.clone())),
+            rules: array_init(|i| {
+                let i = u32::try_from(i).unwrap();
+                f(
+                    ParticleKind(i / ParticleKind::MAX),
+                    ParticleKind(i % ParticleKind::MAX),
+                )
+            }),
         }
     }
 
-    fn get_rule(&self, a: ParticleKind, b: ParticleKind) -> &Rule {
+    #[must_use]
+    pub fn get_rule(&self, a: ParticleKind, b: ParticleKind) -> &Rule {
         &self.rules[(a.0 * ParticleKind::MAX + b.0) as usize]
     }
+
+    pub fn get_rule_mut(&mut self, a: ParticleKind, b: ParticleKind) -> &mut Rule {
+        &mut self.rules[(a.0 * ParticleKind::MAX + b.0) as usize]
+    }
 }
 
 #[repr(C)]
 #[derive(Clone, Copy)]
-struct Rule {
+pub struct Rule {
     pub force: f32,
     pub min_distance: f32,
     pub max_distance: f32,
 }
 
-#[derive(Clone)]
-pub struct RuleGenerationParameters {
-    pub min_distance: Range<f32>,
-    pub max_distance: Range<f32>,
-    pub force: Range<f32>,
-}
+impl Rule {
+    /// How much stronger the repulsion inside `min_distance` is than the
+    /// attraction. Must stay in sync with `REPULSION_SCALE` in
+    /// `particle_life.hlsl`, which a test checks.
+    const REPULSION_SCALE: f32 = 3.0;
+
+    /// The force a particle feels towards another particle that is `distance`
+    /// away. Positive values attract, negative values repel. This mirrors the
+    /// calculation in `particle_life.hlsl`.
+    #[must_use]
+    pub fn force_at(&self, distance: f32) -> f32 {
+        let mut force = 0.0;
+
+        if distance < self.min_distance {
+            force -=
+                self.force.abs() * (1.0 - distance / self.min_distance) * Self::REPULSION_SCALE;
+        }
 
-impl Default for RuleGenerationParameters {
-    fn default() -> Self {
-        Self {
-            min_distance: 30.0_f32..50.0_f32,
-            max_distance: 70.0_f32..250.0_f32,
-            force: 0.3_f32..1.0_f32,
+        if distance < self.max_distance {
+            force += self.force * (1.0 - distance / self.max_distance);
         }
+
+        force
+    }
+
+    /// Samples `force_at` at `num_samples` evenly spaced distances from 0 to
+    /// `end` inclusive, returning (distance, force) pairs.
+    #[allow(clippy::cast_precision_loss)]
+    #[must_use]
+    pub fn force_curve(&self, end: f32, num_samples: usize) -> Vec<(f32, f32)> {
+        let last = num_samples.saturating_sub(1).max(1) as f32;
+
+        (0..num_samples)
+            .map(|i| {
+                let distance = end * (i as f32 / last);
+                (distance, self.force_at(distance))
+            })
+            .collect()
     }
 }
 
-impl Rule {
-    fn new_random(params: RuleGenerationParameters) -> Self {
-        let mut rng = rng();
+#[cfg(test)]
+// The curve is sampled at exact points, so exact comparisons are intended.
+#[allow(clippy::float_cmp)]
+mod tests {
+    use super::Rule;
 
-        let min_distance = rng.random_range(params.min_distance);
-        let max_distance = min_distance + rng.random_range(params.max_distance);
+    const RULE: Rule = Rule {
+        force: 0.5,
+        min_distance: 20.0,
+        max_distance: 100.0,
+    };
 
-        Rule {
-            force: rng.random_range(params.force) * if rng.random_bool(0.5) { -1.0 } else { 1.0 },
-            min_distance,
-            max_distance,
-        }
+    #[test]
+    fn repels_inside_min_distance() {
+        // Right on top of another particle, the full repulsion applies along
+        // with the full attraction.
+        let expected = -0.5 * Rule::REPULSION_SCALE + 0.5;
+        assert!((RULE.force_at(0.0) - expected).abs() < 1e-6);
+
+        // Repulsion applies even when the rule attracts.
+        assert!(RULE.force_at(1.0) < 0.0);
+
+        // Halfway to min_distance, half the repulsion is left.
+        let expected = -0.5 * 0.5 * Rule::REPULSION_SCALE + 0.5 * 0.9;
+        assert!((RULE.force_at(10.0) - expected).abs() < 1e-6);
     }
-}
 
-fn create_root_signature(device: &ID3D12Device) -> ID3D12RootSignature {
-    let rs = include_bytes!(concat!(env!("OUT_DIR"), "/particle_life.root_signature"));
-    unsafe { device.CreateRootSignature(0, rs).unwrap() }
-}
+    #[test]
+    fn no_force_from_max_distance() {
+        assert_eq!(RULE.force_at(100.0), 0.0);
+        assert_eq!(RULE.force_at(150.0), 0.0);
 
-fn create_pipeline_state(device: &ID3D12Device, rs: &ID3D12RootSignature) -> ID3D12PipelineState {
-    let dxil = include_bytes!(concat!(env!("OUT_DIR"), "/particle_life.dxil"));
+        let repel = Rule {
+            force: -0.5,
+            ..RULE
+        };
+        assert_eq!(repel.force_at(100.0), 0.0);
+    }
 
-    let desc = D3D12_COMPUTE_PIPELINE_STATE_DESC {
-        pRootSignature: unsafe { std::mem::transmute_copy(rs) },
-        CS: ShaderBytecode::from(dxil.as_slice()).into(),
-        ..Default::default()
-    };
+    #[test]
+    fn force_curve_samples_both_ends() {
+        let curve = RULE.force_curve(200.0, 5);
+        assert_eq!(curve.len(), 5);
+        assert_eq!(curve[0], (0.0, RULE.force_at(0.0)));
+        assert_eq!(curve[4], (200.0, RULE.force_at(200.0)));
+        assert_eq!(curve[2].0, 100.0);
+    }
+
+    #[test]
+    fn force_curve_with_few_samples() {
+        assert!(RULE.force_curve(200.0, 0).is_empty());
+        assert_eq!(RULE.force_curve(200.0, 1), vec![(0.0, RULE.force_at(0.0))]);
+    }
 
-    unsafe { device.CreateComputePipelineState(&desc).unwrap() }
+    #[test]
+    fn repulsion_scale_matches_shader() {
+        let shader = include_str!("particle_life.hlsl");
+        let expected = format!(
+            "static const float REPULSION_SCALE = {:.1}f;",
+            Rule::REPULSION_SCALE
+        );
+        assert!(
+            shader.lines().any(|line| line.trim() == expected),
+            "particle_life.hlsl should contain `{expected}`"
+        );
+    }
 }
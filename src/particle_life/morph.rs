@@ -0,0 +1,208 @@
+use std::time::{Duration, Instant};
+
+use vek::Lerp;
+
+use super::{Rule, Rules};
+
+/// A timed transition from one set of rules to another. Rather than swapping
+/// rules instantly, the world is driven by a blend of the two that moves from
+/// `from` to `to` over `duration`, so that structures deform gradually.
+pub struct RuleMorph {
+    from: Rules,
+    to: Rules,
+    start: Instant,
+    duration: Duration,
+}
+
+impl RuleMorph {
+    #[must_use]
+    pub fn new(from: &Rules, to: &Rules, duration: Duration) -> Self {
+        RuleMorph {
+            from: *from,
+            to: *to,
+            start: Instant::now(),
+            duration,
+        }
+    }
+
+    /// How far through the transition we are, from 0 to 1.
+    #[must_use]
+    pub fn progress(&self) -> f32 {
+        if self.duration.is_zero() {
+            return 1.0;
+        }
+
+        (self.start.elapsed().as_secs_f32() / self.duration.as_secs_f32()).min(1.0)
+    }
+
+    #[must_use]
+    pub fn is_finished(&self) -> bool {
+        self.progress() >= 1.0
+    }
+
+    /// The rules for the current point in the transition.
+    #[must_use]
+    pub fn current(&self) -> Rules {
+        self.rules_at(self.progress())
+    }
+
+    /// The rules at `progress`, from 0 to 1, through the transition. Eases in
+    /// and out so that the world doesn't lurch at either end.
+    #[must_use]
+    pub fn rules_at(&self, progress: f32) -> Rules {
+        self.from.lerp(&self.to, ease(progress.clamp(0.0, 1.0)))
+    }
+
+    /// Moves `rules` on to the current point in the transition and returns
+    /// whether there is more to come. If the rules were `edited` since the last
+    /// step the edits are kept instead, since they were made to the rules the
+    /// morph had reached, and the morph stops.
+    #[must_use]
+    pub fn step(&self, rules: &mut Rules, edited: bool) -> bool {
+        if edited {
+            return false;
+        }
+
+        let progress = self.progress();
+        *rules = self.rules_at(progress);
+        progress < 1.0
+    }
+
+    #[must_use]
+    pub fn target(&self) -> &Rules {
+        &self.to
+    }
+}
+
+/// Smoothstep: starts and ends with zero slope.
+fn ease(t: f32) -> f32 {
+    t * t * (3.0 - 2.0 * t)
+}
+
+impl Rules {
+    /// Blends every rule linearly between `self` (at `t = 0`) and `other` (at
+    /// `t = 1`), landing exactly on `other` at the end. Since each rule has
+    /// `min_distance <= max_distance` at both ends, the blend does too.
+    #[must_use]
+    pub fn lerp(&self, other: &Rules, t: f32) -> Rules {
+        Rules::from_fn(|a, b| {
+            let from = self.get_rule(a, b);
+            let to = other.get_rule(a, b);
+
+            Rule {
+                force: Lerp::lerp_precise(from.force, to.force, t),
+                min_distance: Lerp::lerp_precise(from.min_distance, to.min_distance, t),
+                max_distance: Lerp::lerp_precise(from.max_distance, to.max_distance, t),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+// The end points of a blend are the rules themselves, so exact comparisons
+// are intended.
+#[allow(clippy::float_cmp)]
+mod tests {
+    use std::time::Duration;
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::{ease, RuleMorph};
+    use crate::particle_life::{ParticleKind, RuleGenerationParameters, Rules};
+
+    fn two_rule_sets() -> (Rules, Rules) {
+        let params = RuleGenerationParameters::default();
+        let mut rng = StdRng::seed_from_u64(1);
+        (
+            Rules::new_random_with_rng(&params, &mut rng),
+            Rules::new_random_with_rng(&params, &mut rng),
+        )
+    }
+
+    fn same(a: &Rules, b: &Rules) -> bool {
+        ParticleKind::all().all(|x| {
+            ParticleKind::all().all(|y| {
+                let (a, b) = (a.get_rule(x, y), b.get_rule(x, y));
+                a.force == b.force
+                    && a.min_distance == b.min_distance
+                    && a.max_distance == b.max_distance
+            })
+        })
+    }
+
+    #[test]
+    fn lerp_end_points() {
+        let (from, to) = two_rule_sets();
+
+        assert!(same(&from.lerp(&to, 0.0), &from));
+        assert!(same(&from.lerp(&to, 1.0), &to));
+
+        let halfway = from.lerp(&to, 0.5);
+        for a in ParticleKind::all() {
+            for b in ParticleKind::all() {
+                let (from, to, mid) = (
+                    from.get_rule(a, b),
+                    to.get_rule(a, b),
+                    halfway.get_rule(a, b),
+                );
+                assert!((mid.force - f32::midpoint(from.force, to.force)).abs() < 1e-5);
+                assert!(mid.min_distance <= mid.max_distance);
+            }
+        }
+    }
+
+    #[test]
+    fn easing_curve() {
+        assert_eq!(ease(0.0), 0.0);
+        assert_eq!(ease(0.5), 0.5);
+        assert_eq!(ease(1.0), 1.0);
+
+        // Slow at both ends, and always moving forwards
+        assert!(ease(0.1) < 0.1);
+        assert!(ease(0.9) > 0.9);
+        let samples: Vec<f32> = (0..=100).map(|i| ease(i as f32 / 100.0)).collect();
+        assert!(samples.windows(2).all(|pair| pair[0] <= pair[1]));
+    }
+
+    #[test]
+    fn rules_follow_the_eased_progress() {
+        let (from, to) = two_rule_sets();
+        let morph = RuleMorph::new(&from, &to, Duration::from_secs(10));
+
+        assert!(same(&morph.rules_at(0.0), &from));
+        assert!(same(&morph.rules_at(1.0), &to));
+        assert!(same(&morph.rules_at(0.25), &from.lerp(&to, ease(0.25))));
+        // Out of range progress is held at the ends
+        assert!(same(&morph.rules_at(2.0), &to));
+    }
+
+    #[test]
+    fn step_moves_the_rules_until_finished() {
+        let (from, to) = two_rule_sets();
+
+        let morph = RuleMorph::new(&from, &to, Duration::from_secs(100));
+        let mut rules = to;
+        assert!(morph.step(&mut rules, false));
+        // Barely started, so close to where it came from
+        let force = |rules: &Rules| rules.get_rule(ParticleKind(0), ParticleKind(1)).force;
+        assert!((force(&rules) - force(&from)).abs() < 1e-3);
+
+        let finished = RuleMorph::new(&from, &to, Duration::ZERO);
+        let mut rules = from;
+        assert!(!finished.step(&mut rules, false));
+        assert!(same(&rules, &to));
+    }
+
+    #[test]
+    fn editing_stops_the_morph_and_keeps_the_edits() {
+        let (from, to) = two_rule_sets();
+        let morph = RuleMorph::new(&from, &to, Duration::from_secs(100));
+
+        let mut rules = morph.current();
+        rules.get_rule_mut(ParticleKind(2), ParticleKind(3)).force = 0.25;
+        let edited = rules;
+
+        assert!(!morph.step(&mut rules, true));
+        assert!(same(&rules, &edited));
+    }
+}
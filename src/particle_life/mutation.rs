@@ -0,0 +1,185 @@
+use rand::Rng;
+use rand_distr::{Distribution, Normal};
+
+use super::{Rule, RuleGenerationParameters, Rules};
+
+#[derive(Clone)]
+pub struct MutationParameters {
+    /// Standard deviation of the noise added to each rule. Forces are nudged
+    /// by this amount, distances are scaled by a factor of roughly
+    /// `1 +/- amount`.
+    pub amount: f32,
+    /// Fraction of the rules that are replaced by completely new random rules
+    /// rather than being nudged.
+    pub randomize_fraction: f32,
+}
+
+impl Default for MutationParameters {
+    fn default() -> Self {
+        Self {
+            amount: 0.1,
+            randomize_fraction: 0.05,
+        }
+    }
+}
+
+impl Rules {
+    /// Returns a copy of these rules with every rule perturbed slightly, and
+    /// some replaced outright, so that the world drifts towards a variant of
+    /// the current one.
+    #[must_use]
+    pub fn mutate(
+        &self,
+        mutation: &MutationParameters,
+        params: &RuleGenerationParameters,
+        rng: &mut impl Rng,
+    ) -> Rules {
+        let noise = Normal::new(0.0, mutation.amount.max(0.0)).unwrap();
+
+        let mut rules = Rules::from_fn(|a, b| {
+            let rule = self.get_rule(a, b);
+
+            let min_distance = (rule.min_distance * noise.sample(rng).exp()).max(0.001);
+            let max_distance = (rule.max_distance * noise.sample(rng).exp()).max(min_distance);
+
+            Rule {
+                force: (rule.force + noise.sample(rng)).clamp(-2.0, 2.0),
+                min_distance,
+                max_distance,
+            }
+        });
+
+        rules.randomize_fraction(mutation.randomize_fraction, params, rng);
+        rules
+    }
+
+    /// Randomizes roughly `fraction` of the rules, leaving the rest untouched.
+    pub fn randomize_fraction(
+        &mut self,
+        fraction: f32,
+        params: &RuleGenerationParameters,
+        rng: &mut impl Rng,
+    ) {
+        let fraction = f64::from(fraction.clamp(0.0, 1.0));
+
+        *self = Rules::from_fn(|a, b| {
+            if rng.random_bool(fraction) {
+                Rule::new_random(params, rng)
+            } else {
+                *self.get_rule(a, b)
+            }
+        });
+    }
+
+    /// Uniform crossover: each rule of the child is taken from one of the two
+    /// parents with equal probability.
+    #[must_use]
+    pub fn crossover(&self, other: &Rules, rng: &mut impl Rng) -> Rules {
+        Rules::from_fn(|a, b| {
+            if rng.random_bool(0.5) {
+                *self.get_rule(a, b)
+            } else {
+                *other.get_rule(a, b)
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+// Crossover copies rules and clamping lands exactly on the limits, so exact
+// comparisons are intended.
+#[allow(clippy::float_cmp)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::MutationParameters;
+    use crate::particle_life::{ParticleKind, Rule, RuleGenerationParameters, Rules};
+
+    fn pairs() -> impl Iterator<Item = (ParticleKind, ParticleKind)> {
+        ParticleKind::all().flat_map(|a| ParticleKind::all().map(move |b| (a, b)))
+    }
+
+    fn same(a: &Rule, b: &Rule) -> bool {
+        a.force == b.force && a.min_distance == b.min_distance && a.max_distance == b.max_distance
+    }
+
+    #[test]
+    fn mutation_keeps_rules_valid() {
+        let params = RuleGenerationParameters::default();
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut rules = Rules::new_random_with_rng(&params, &mut rng);
+
+        // Far more noise than is useful, so that the limits are reached
+        let mutation = MutationParameters {
+            amount: 5.0,
+            randomize_fraction: 0.0,
+        };
+        let mut clamped = false;
+        for _ in 0..20 {
+            rules = rules.mutate(&mutation, &params, &mut rng);
+
+            for (a, b) in pairs() {
+                let rule = rules.get_rule(a, b);
+                assert!((-2.0..=2.0).contains(&rule.force), "force {}", rule.force);
+                assert!(rule.min_distance >= 0.001);
+                assert!(rule.min_distance <= rule.max_distance);
+                clamped |= rule.force.abs() == 2.0 || rule.min_distance == rule.max_distance;
+            }
+        }
+        assert!(clamped);
+    }
+
+    #[test]
+    fn mutation_without_noise_only_randomizes() {
+        let params = RuleGenerationParameters::default();
+        let mut rng = StdRng::seed_from_u64(1);
+        let rules = Rules::new_random_with_rng(&params, &mut rng);
+
+        let unchanged = rules.mutate(
+            &MutationParameters {
+                amount: 0.0,
+                randomize_fraction: 0.0,
+            },
+            &params,
+            &mut rng,
+        );
+        assert!(pairs().all(|(a, b)| same(unchanged.get_rule(a, b), rules.get_rule(a, b))));
+
+        let replaced = rules.mutate(
+            &MutationParameters {
+                amount: 0.0,
+                randomize_fraction: 1.0,
+            },
+            &params,
+            &mut rng,
+        );
+        assert!(pairs().all(|(a, b)| !same(replaced.get_rule(a, b), rules.get_rule(a, b))));
+        for (a, b) in pairs() {
+            let rule = replaced.get_rule(a, b);
+            assert!(rule.min_distance <= rule.max_distance);
+        }
+    }
+
+    #[test]
+    fn crossover_takes_each_rule_from_a_parent() {
+        let params = RuleGenerationParameters::default();
+        let mut rng = StdRng::seed_from_u64(1);
+        let mother = Rules::new_random_with_rng(&params, &mut rng);
+        let father = Rules::new_random_with_rng(&params, &mut rng);
+
+        let child = mother.crossover(&father, &mut rng);
+
+        let (mut from_mother, mut from_father) = (0, 0);
+        for (a, b) in pairs() {
+            let rule = child.get_rule(a, b);
+            if same(rule, mother.get_rule(a, b)) {
+                from_mother += 1;
+            } else {
+                assert!(same(rule, father.get_rule(a, b)));
+                from_father += 1;
+            }
+            assert!(rule.min_distance <= rule.max_distance);
+        }
+        assert!(from_mother > 0 && from_father > 0);
+    }
+}
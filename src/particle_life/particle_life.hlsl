@@ -4,7 +4,8 @@
     "SRV(t0)," \
     "SRV(t1)," \
     "UAV(u0)," \
-    "UAV(u1)"
+    "UAV(u1)," \
+    "SRV(t2)"
     
 
 cbuffer CONSTANTS : register(b0) {
@@ -13,8 +14,13 @@ cbuffer CONSTANTS : register(b0) {
     float2 WorldSize;
     float Friction;
     float ForceMultipler;
+    uint UseColorOverride;
 }
 
+// How much stronger the repulsion inside min_distance is than the attraction.
+// Rule::REPULSION_SCALE in mod.rs must have the same value.
+static const float REPULSION_SCALE = 3.0f;
+
 struct Rule {
     float force;
     float min_distance;
@@ -25,6 +31,8 @@ struct Particle {
     float2 position;
     float2 velocity;
     uint type;
+    uint neighbours;
+    uint id;
 };
 
 struct Vertex {
@@ -36,6 +44,7 @@ StructuredBuffer<Rule> Rules : register(t0);
 StructuredBuffer<Particle> OldParticles : register(t1);
 RWStructuredBuffer<Particle> NewParticles : register(u0);
 RWStructuredBuffer<Vertex> Vertices : register(u1);
+StructuredBuffer<uint> ColorOverride : register(t2);
 
 
 float3 particle_type_to_color(uint type);
@@ -45,12 +54,15 @@ uint float_to_abgr(float3 rgb);
 [numthreads(32, 1, 1)]
 void main(uint3 dispatch_thread_id : SV_DispatchThreadID) {
     uint particle_id = dispatch_thread_id.x;
+    if (particle_id >= NumParticles)
+        return;
 
     Particle particle = OldParticles[particle_id];
     
     // Accumulate forces
     float2 force = float2(0,0);
     float hit = 0;
+    uint neighbours = 0;
 
     for (uint i = 0; i < NumParticles; ++i) {
         if (i == particle_id)
@@ -77,7 +89,7 @@ void main(uint3 dispatch_thread_id : SV_DispatchThreadID) {
         direction = normalize(direction);
 
         if (distance < rule.min_distance) {
-            float repulsive_amount = abs(rule.force) * (1.0f - (distance / rule.min_distance))  * -3.0f;
+            float repulsive_amount = abs(rule.force) * (1.0f - (distance / rule.min_distance)) * -REPULSION_SCALE;
             force += direction * repulsive_amount;
         }
 
@@ -85,6 +97,7 @@ void main(uint3 dispatch_thread_id : SV_DispatchThreadID) {
             float attract_amount = rule.force * (1.0f - (distance / rule.max_distance));
             force += direction * attract_amount;
             hit += 0.01f;
+            neighbours += 1;
         }
     }
 
@@ -108,6 +121,7 @@ void main(uint3 dispatch_thread_id : SV_DispatchThreadID) {
 
 
     particle.velocity = velocity;
+    particle.neighbours = neighbours;
 
     Vertices[particle_id].position = particle.position;
 
@@ -115,7 +129,10 @@ void main(uint3 dispatch_thread_id : SV_DispatchThreadID) {
 
     color = lerp(color, color * 0.1f, 1-saturate(hit));
 
-    Vertices[particle_id].color = float_to_abgr(color);
+    if (UseColorOverride)
+        Vertices[particle_id].color = ColorOverride[particle_id];
+    else
+        Vertices[particle_id].color = float_to_abgr(color);
 
     NewParticles[particle_id] = particle;
 }
@@ -0,0 +1,227 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{ensure, Context, Error, Result};
+use serde::{Deserialize, Serialize};
+
+use super::{ParticleKind, Rule, Rules, ShaderGlobalConstants};
+
+/// Directory, relative to the working directory, that user presets are loaded
+/// from and saved to.
+pub const USER_PRESETS_DIR: &str = "presets";
+
+const BUILT_IN_PRESETS: [&str; 5] = [
+    include_str!("presets/cells.toml"),
+    include_str!("presets/snakes.toml"),
+    include_str!("presets/spirals.toml"),
+    include_str!("presets/clusters.toml"),
+    include_str!("presets/gliders.toml"),
+];
+
+/// A named set of rules, along with the world settings they were tuned for.
+#[derive(Clone)]
+pub struct Preset {
+    pub name: String,
+    pub description: String,
+    pub friction: f32,
+    pub force_multiplier: f32,
+    pub rules: Rules,
+    /// File the preset was loaded from or last saved to, if any.
+    pub path: Option<PathBuf>,
+}
+
+/// On-disk representation of a preset. Each rule parameter is stored as a
+/// square matrix indexed by [kind][other kind] so that files are easy to read
+/// and edit by hand.
+#[derive(Serialize, Deserialize)]
+struct PresetFile {
+    name: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    description: String,
+    friction: f32,
+    force_multiplier: f32,
+    force: Vec<Vec<f32>>,
+    min_distance: Vec<Vec<f32>>,
+    max_distance: Vec<Vec<f32>>,
+}
+
+impl Preset {
+    #[must_use]
+    pub fn new(name: &str, settings: &ShaderGlobalConstants, rules: &Rules) -> Self {
+        Preset {
+            name: name.to_owned(),
+            description: String::new(),
+            friction: settings.friction,
+            force_multiplier: settings.force_multiplier,
+            rules: *rules,
+            path: None,
+        }
+    }
+
+    pub fn apply_settings(&self, settings: &mut ShaderGlobalConstants) {
+        settings.friction = self.friction;
+        settings.force_multiplier = self.force_multiplier;
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let file: PresetFile = toml::from_str(text)?;
+
+        let force = check_matrix("force", file.force)?;
+        let min_distance = check_matrix("min_distance", file.min_distance)?;
+        let max_distance = check_matrix("max_distance", file.max_distance)?;
+
+        for (a, row) in min_distance.iter().enumerate() {
+            for (b, min) in row.iter().enumerate() {
+                let max = max_distance[a][b];
+                ensure!(
+                    *min > 0.0 && *min <= max,
+                    "rule [{a}][{b}]: expected 0 < min_distance <= max_distance, got {min} and {max}"
+                );
+            }
+        }
+
+        let rules = Rules::from_fn(|a, b| {
+            let (a, b) = (a.0 as usize, b.0 as usize);
+            Rule {
+                force: force[a][b],
+                min_distance: min_distance[a][b],
+                max_distance: max_distance[a][b],
+            }
+        });
+
+        Ok(Preset {
+            name: file.name,
+            description: file.description,
+            friction: file.friction,
+            force_multiplier: file.force_multiplier,
+            rules,
+            path: None,
+        })
+    }
+
+    #[must_use]
+    pub fn to_toml(&self) -> String {
+        let matrix = |f: fn(&Rule) -> f32| -> Vec<Vec<f32>> {
+            (0..ParticleKind::MAX)
+                .map(|a| {
+                    (0..ParticleKind::MAX)
+                        .map(|b| f(self.rules.get_rule(ParticleKind(a), ParticleKind(b))))
+                        .collect()
+                })
+                .collect()
+        };
+
+        let file = PresetFile {
+            name: self.name.clone(),
+            description: self.description.clone(),
+            friction: self.friction,
+            force_multiplier: self.force_multiplier,
+            force: matrix(|r| r.force),
+            min_distance: matrix(|r| r.min_distance),
+            max_distance: matrix(|r| r.max_distance),
+        };
+
+        toml::to_string(&file).unwrap()
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)?;
+        let mut preset =
+            Self::parse(&text).with_context(|| format!("parsing {}", path.display()))?;
+        preset.path = Some(path.to_owned());
+        Ok(preset)
+    }
+
+    /// Saves the preset into `dir`, at `path_in(dir, name)`. An existing file
+    /// is only replaced if `overwrite` is set. Returns the path that was
+    /// written.
+    pub fn save(&self, dir: &Path, overwrite: bool) -> Result<PathBuf> {
+        let path = Self::path_in(dir, &self.name);
+        ensure!(
+            overwrite || !path.exists(),
+            "{} already exists",
+            path.display()
+        );
+
+        fs::create_dir_all(dir)?;
+        fs::write(&path, self.to_toml())?;
+        Ok(path)
+    }
+
+    /// The file in `dir` that a preset called `name` is saved to.
+    #[must_use]
+    pub fn path_in(dir: &Path, name: &str) -> PathBuf {
+        let file_name: String = name
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() {
+                    c.to_ascii_lowercase()
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        dir.join(file_name).with_extension("toml")
+    }
+}
+
+fn check_matrix(name: &str, matrix: Vec<Vec<f32>>) -> Result<Vec<Vec<f32>>> {
+    let size = ParticleKind::MAX as usize;
+
+    ensure!(
+        matrix.len() == size && matrix.iter().all(|row| row.len() == size),
+        "{name} must be a {size}x{size} matrix"
+    );
+    ensure!(
+        matrix.iter().flatten().all(|v| v.is_finite()),
+        "{name} contains non-finite values"
+    );
+
+    Ok(matrix)
+}
+
+#[must_use]
+pub fn built_in() -> Vec<Preset> {
+    BUILT_IN_PRESETS
+        .iter()
+        .map(|text| Preset::parse(text).expect("built-in presets should be valid"))
+        .collect()
+}
+
+/// Loads every `.toml` file in `dir`. Files that fail to load are skipped, so
+/// that one bad file doesn't hide the rest, and returned as errors.
+#[must_use]
+pub fn load_dir(dir: &Path) -> (Vec<Preset>, Vec<Error>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return (Vec::new(), Vec::new());
+    };
+
+    let mut paths: Vec<_> = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+        .collect();
+    paths.sort();
+
+    let mut presets = Vec::new();
+    let mut errors = Vec::new();
+    for path in &paths {
+        match Preset::load(path) {
+            Ok(preset) => presets.push(preset),
+            Err(e) => errors.push(e.context(format!("loading {}", path.display()))),
+        }
+    }
+
+    (presets, errors)
+}
+
+/// The built-in presets followed by any found in the user presets directory,
+/// along with the errors for any user presets that failed to load.
+#[must_use]
+pub fn all() -> (Vec<Preset>, Vec<Error>) {
+    let (user, errors) = load_dir(Path::new(USER_PRESETS_DIR));
+    let mut presets = built_in();
+    presets.extend(user);
+    (presets, errors)
+}
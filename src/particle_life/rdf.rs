@@ -0,0 +1,236 @@
+use std::{f32::consts::PI, fmt::Write as _};
+
+use vek::Vec2;
+
+use super::{
+    cpu::{wrapped_delta, Grid},
+    Particle, ParticleKind,
+};
+
+const NUM_KINDS: usize = ParticleKind::MAX as usize;
+
+#[derive(Clone)]
+pub struct RdfParameters {
+    /// Largest distance measured. Limited to half the size of the world, past
+    /// which distances wrap around.
+    pub max_distance: f32,
+    pub bins: usize,
+    /// At most this many particles are used as the centres that distances are
+    /// measured from, so that large worlds can be measured quickly. Every
+    /// particle is still counted as a neighbour.
+    pub max_centres: usize,
+}
+
+impl Default for RdfParameters {
+    fn default() -> Self {
+        RdfParameters {
+            max_distance: 150.0,
+            bins: 75,
+            max_centres: 5000,
+        }
+    }
+}
+
+/// The pair correlation function g(r) for every ordered pair of kinds: how
+/// many particles of the second kind are found at distance r from particles of
+/// the first, relative to how many there would be if they were spread evenly.
+/// Peaks show the distances that the rules hold particles at.
+pub struct RadialDistribution {
+    pub max_distance: f32,
+    pub bins: usize,
+    /// Indexed by [kind][other kind][bin].
+    values: Vec<f32>,
+}
+
+impl RadialDistribution {
+    #[allow(
+        clippy::cast_precision_loss,
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss
+    )]
+    #[must_use]
+    pub fn measure(particles: &[Particle], world_size: Vec2<f32>, params: &RdfParameters) -> Self {
+        let max_distance = params
+            .max_distance
+            .min(world_size.reduce_partial_min() * 0.5);
+        let bins = params.bins.max(1);
+        let bin_width = max_distance / bins as f32;
+
+        let mut kind_counts = [0_usize; NUM_KINDS];
+        for particle in particles {
+            kind_counts[particle.kind.index()] += 1;
+        }
+
+        let stride = particles.len().div_ceil(params.max_centres.max(1)).max(1);
+        let mut centre_counts = [0_usize; NUM_KINDS];
+        let mut pair_counts = vec![0_u32; NUM_KINDS * NUM_KINDS * bins];
+
+        let grid = Grid::new(particles, world_size, max_distance);
+        for (index, particle) in particles.iter().enumerate().step_by(stride) {
+            let a = particle.kind.index();
+            centre_counts[a] += 1;
+
+            grid.for_each_neighbour(particle.position, |other_index| {
+                if other_index == index {
+                    return;
+                }
+
+                let other = &particles[other_index];
+                let distance =
+                    wrapped_delta(particle.position, other.position, world_size).magnitude();
+                if distance < max_distance {
+                    let bin = ((distance / bin_width) as usize).min(bins - 1);
+                    pair_counts[(a * NUM_KINDS + other.kind.index()) * bins + bin] += 1;
+                }
+            });
+        }
+
+        // Normalise by the count expected for evenly spread particles
+        let area = world_size.product();
+        let mut values = vec![0.0; pair_counts.len()];
+        for (a, centres) in centre_counts.iter().enumerate() {
+            for (b, count) in kind_counts.iter().enumerate() {
+                let others = count.saturating_sub(usize::from(a == b));
+                let density = others as f32 / area;
+                let centres = *centres as f32;
+
+                for bin in 0..bins {
+                    let inner = bin as f32 * bin_width;
+                    let outer = inner + bin_width;
+                    let shell_area = PI * (outer * outer - inner * inner);
+                    let expected = centres * density * shell_area;
+
+                    let i = (a * NUM_KINDS + b) * bins + bin;
+                    if expected > 0.0 {
+                        values[i] = pair_counts[i] as f32 / expected;
+                    }
+                }
+            }
+        }
+
+        RadialDistribution {
+            max_distance,
+            bins,
+            values,
+        }
+    }
+
+    /// g(r) for particles of kind `b` around particles of kind `a`, one value
+    /// per bin.
+    #[must_use]
+    pub fn get(&self, a: ParticleKind, b: ParticleKind) -> &[f32] {
+        let start = (a.index() * NUM_KINDS + b.index()) * self.bins;
+        &self.values[start..start + self.bins]
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    #[must_use]
+    pub fn bin_centre(&self, bin: usize) -> f32 {
+        (bin as f32 + 0.5) * self.max_distance / self.bins as f32
+    }
+
+    #[must_use]
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("kind,other_kind,r,g\n");
+
+        for a in ParticleKind::all() {
+            for b in ParticleKind::all() {
+                for (bin, g) in self.get(a, b).iter().enumerate() {
+                    writeln!(
+                        csv,
+                        "{},{},{},{g}",
+                        a.index(),
+                        b.index(),
+                        self.bin_centre(bin)
+                    )
+                    .unwrap();
+                }
+            }
+        }
+
+        csv
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use vek::Vec2;
+
+    use super::{RadialDistribution, RdfParameters};
+    use crate::particle_life::{cpu::CpuWorld, Particle, ParticleKind};
+
+    #[test]
+    fn evenly_spread_particles_are_uncorrelated() {
+        let world = CpuWorld::from_seed(4000, Vec2::new(400.0, 300.0), 2, 1);
+        let params = RdfParameters {
+            max_distance: 50.0,
+            bins: 10,
+            max_centres: 5000,
+        };
+        let rdf = RadialDistribution::measure(world.particles(), world.world_size(), &params);
+
+        for (a, b) in [(0, 0), (0, 1), (1, 0), (1, 1)] {
+            for (bin, g) in rdf.get(ParticleKind(a), ParticleKind(b)).iter().enumerate() {
+                assert!((g - 1.0).abs() < 0.15, "g({a}, {b}) is {g} in bin {bin}");
+            }
+        }
+        // Kinds that aren't present have nothing to measure
+        assert!(rdf
+            .get(ParticleKind(0), ParticleKind(2))
+            .iter()
+            .all(|g| *g == 0.0));
+    }
+
+    #[test]
+    fn each_pair_of_kinds_is_normalised_by_its_own_counts() {
+        let particles: Vec<Particle> = [
+            (50.0, 50.0, 0),
+            (53.0, 50.0, 1),
+            (50.0, 57.0, 1),
+            (10.0, 10.0, 1),
+        ]
+        .into_iter()
+        .zip(0..)
+        .map(|((x, y, kind), id)| Particle {
+            position: Vec2::new(x, y),
+            velocity: Vec2::zero(),
+            kind: ParticleKind(kind),
+            neighbours: 0,
+            id,
+        })
+        .collect();
+        let params = RdfParameters {
+            max_distance: 10.0,
+            bins: 2,
+            max_centres: 10,
+        };
+        let rdf = RadialDistribution::measure(&particles, Vec2::new(100.0, 100.0), &params);
+
+        // g = pairs found / (centres * density of the other kind * shell area)
+        let shells = [PI * 25.0, PI * 75.0];
+        let g = |pairs: f32, centres: f32, others: f32, bin: usize| {
+            pairs / (centres * others / 10_000.0 * shells[bin])
+        };
+        let assert_g = |a: u32, b: u32, expected: [f32; 2]| {
+            let values = rdf.get(ParticleKind(a), ParticleKind(b));
+            for (value, expected) in values.iter().zip(expected) {
+                assert!(
+                    (value - expected).abs() < expected.max(1.0) * 1e-4,
+                    "g({a}, {b}) is {values:?}, expected {expected}"
+                );
+            }
+        };
+
+        // The kind 0 particle has one kind 1 particle in each bin
+        assert_g(0, 1, [g(1.0, 1.0, 3.0, 0), g(1.0, 1.0, 3.0, 1)]);
+        // and each of those sees it once, from three kind 1 centres
+        assert_g(1, 0, [g(1.0, 3.0, 1.0, 0), g(1.0, 3.0, 1.0, 1)]);
+        // The two nearby kind 1 particles see each other in the outer bin,
+        // with two others of their kind around each centre
+        assert_g(1, 1, [0.0, g(2.0, 3.0, 2.0, 1)]);
+        // A lone particle has no others of its kind
+        assert_g(0, 0, [0.0, 0.0]);
+    }
+}
@@ -0,0 +1,514 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, ensure, Context, Result};
+use rand::{rngs::StdRng, SeedableRng};
+use serde::Deserialize;
+use vek::Vec2;
+
+use super::{
+    classify::{classify, Behaviour, CLASSIFICATION_WINDOW, MIN_SAMPLES},
+    cpu::{self, CpuWorld},
+    fitness,
+    metrics::Metrics,
+    presets::Preset,
+    ParticleKind, RuleGenerationParameters, Rules, ShaderGlobalConstants,
+};
+
+/// A reproducible run: where the particles start, the rules and settings,
+/// changes to make partway through, and what the metrics should look like at
+/// checkpoints along the way.
+pub struct Scenario {
+    pub name: String,
+    pub description: String,
+    pub seed: u64,
+    pub particles: usize,
+    pub species: u32,
+    pub world_size: Vec2<f32>,
+    pub steps: usize,
+    preset: Option<Preset>,
+    friction: Option<f32>,
+    force_multiplier: Option<f32>,
+    changes: Vec<Change>,
+    checkpoints: Vec<Checkpoint>,
+}
+
+/// Settings to change once `step` steps have run.
+struct Change {
+    step: usize,
+    preset: Option<Preset>,
+    friction: Option<f32>,
+    force_multiplier: Option<f32>,
+}
+
+/// Bounds on the metrics, averaged over the `window` steps up to and
+/// including `step`.
+struct Checkpoint {
+    step: usize,
+    window: usize,
+    metrics: BTreeMap<String, Bounds>,
+    /// Any of these is accepted. Empty to not check the behaviour.
+    behaviour: Vec<Behaviour>,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+struct Bounds {
+    min: Option<f32>,
+    max: Option<f32>,
+}
+
+/// The result of checking one bound at a checkpoint.
+pub struct Check {
+    pub description: String,
+    pub passed: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ScenarioFile {
+    name: String,
+    #[serde(default)]
+    description: String,
+    seed: u64,
+    particles: usize,
+    species: Option<u32>,
+    world_size: Option<[f32; 2]>,
+    /// Defaults to the last change or checkpoint.
+    steps: Option<usize>,
+    /// Relative to the scenario file.
+    preset: Option<PathBuf>,
+    friction: Option<f32>,
+    force_multiplier: Option<f32>,
+    #[serde(default)]
+    changes: Vec<ChangeFile>,
+    #[serde(default)]
+    checkpoints: Vec<CheckpointFile>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ChangeFile {
+    step: usize,
+    preset: Option<PathBuf>,
+    friction: Option<f32>,
+    force_multiplier: Option<f32>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CheckpointFile {
+    step: usize,
+    #[serde(default = "default_window")]
+    window: usize,
+    #[serde(default)]
+    metrics: BTreeMap<String, Bounds>,
+    #[serde(default)]
+    behaviour: Vec<String>,
+}
+
+fn default_window() -> usize {
+    1
+}
+
+impl Scenario {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text =
+            fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+        let file: ScenarioFile =
+            toml::from_str(&text).with_context(|| format!("parsing {}", path.display()))?;
+        let dir = path.parent().unwrap_or(Path::new(""));
+        Self::from_file(file, dir).with_context(|| format!("checking {}", path.display()))
+    }
+
+    fn from_file(file: ScenarioFile, dir: &Path) -> Result<Self> {
+        let load_preset = |preset: Option<PathBuf>| {
+            preset
+                .map(|preset| {
+                    let path = dir.join(preset);
+                    Preset::load(&path)
+                        .with_context(|| format!("loading preset {}", path.display()))
+                })
+                .transpose()
+        };
+
+        ensure!(file.particles > 0, "particles must be at least 1");
+
+        let species = file.species.unwrap_or(ParticleKind::MAX);
+        ensure!(
+            (1..=ParticleKind::MAX).contains(&species),
+            "species must be between 1 and {}, got {species}",
+            ParticleKind::MAX
+        );
+
+        let world_size = file
+            .world_size
+            .map_or_else(|| cpu::default_world_size(file.particles), Vec2::from);
+        ensure!(
+            world_size
+                .iter()
+                .all(|size| size.is_finite() && *size > 0.0),
+            "world size must be greater than zero, got {}x{}",
+            world_size.x,
+            world_size.y
+        );
+
+        let last_step = file
+            .changes
+            .iter()
+            .map(|change| change.step)
+            .chain(file.checkpoints.iter().map(|checkpoint| checkpoint.step))
+            .max()
+            .unwrap_or(0);
+        let steps = file.steps.unwrap_or(last_step);
+        ensure!(steps > 0, "steps must be at least 1");
+
+        let mut changes = file
+            .changes
+            .into_iter()
+            .map(|change| {
+                ensure!(
+                    change.step < steps,
+                    "change at step {} is after the last step, {steps}",
+                    change.step
+                );
+                Ok(Change {
+                    step: change.step,
+                    preset: load_preset(change.preset)?,
+                    friction: change.friction,
+                    force_multiplier: change.force_multiplier,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        changes.sort_by_key(|change| change.step);
+
+        let mut checkpoints = file
+            .checkpoints
+            .into_iter()
+            .map(|checkpoint| Checkpoint::from_file(checkpoint, steps))
+            .collect::<Result<Vec<_>>>()?;
+        checkpoints.sort_by_key(|checkpoint| checkpoint.step);
+
+        Ok(Scenario {
+            name: file.name,
+            description: file.description,
+            seed: file.seed,
+            particles: file.particles,
+            species,
+            world_size,
+            steps,
+            preset: load_preset(file.preset)?,
+            friction: file.friction,
+            force_multiplier: file.force_multiplier,
+            changes,
+            checkpoints,
+        })
+    }
+
+    /// Builds the world and rules the scenario starts with. The same scenario
+    /// always gives the same world, which is the one the viewer makes from the
+    /// same seed.
+    #[must_use]
+    pub fn create_world(&self) -> (CpuWorld, Rules) {
+        let rules = self.preset.as_ref().map_or_else(
+            || {
+                let params = RuleGenerationParameters {
+                    species: self.species,
+                    ..Default::default()
+                };
+                Rules::new_random_with_rng(&params, &mut StdRng::seed_from_u64(self.seed))
+            },
+            |preset| preset.rules,
+        );
+
+        let mut world =
+            CpuWorld::from_seed(self.particles, self.world_size, self.species, self.seed);
+        apply_settings(
+            world.settings(),
+            self.preset.as_ref(),
+            self.friction,
+            self.force_multiplier,
+        );
+
+        (world, rules)
+    }
+
+    /// Makes the changes that are due once `steps_done` steps have run.
+    pub fn apply_changes(&self, steps_done: usize, world: &mut CpuWorld, rules: &mut Rules) {
+        for change in self
+            .changes
+            .iter()
+            .filter(|change| change.step == steps_done)
+        {
+            if let Some(preset) = &change.preset {
+                *rules = preset.rules;
+            }
+            apply_settings(
+                world.settings(),
+                change.preset.as_ref(),
+                change.friction,
+                change.force_multiplier,
+            );
+        }
+    }
+
+    /// Checks the checkpoints at `step`, given the metrics for every step so
+    /// far. Returns nothing if there are no checkpoints at `step`.
+    #[must_use]
+    pub fn check(&self, step: usize, history: &[Metrics]) -> Vec<Check> {
+        self.checkpoints
+            .iter()
+            .filter(|checkpoint| checkpoint.step == step)
+            .flat_map(|checkpoint| checkpoint.check(history))
+            .collect()
+    }
+}
+
+/// One step of `simulate`, just after it has run.
+pub struct StepReport<'a> {
+    pub step: usize,
+    pub world: &'a CpuWorld,
+    pub metrics: Metrics,
+    /// False if the particles' positions or velocities have stopped being
+    /// finite, which ends the simulation.
+    pub finite: bool,
+    /// The scenario's checks at this step, if it has a checkpoint here.
+    pub checks: Vec<Check>,
+}
+
+/// What happened over a whole simulation.
+pub struct Simulation {
+    /// The metrics at every step that ran, oldest first.
+    pub history: Vec<Metrics>,
+    /// Every check the scenario made.
+    pub checks: Vec<Check>,
+    /// The step the particles stopped being finite at, if they did.
+    pub non_finite_step: Option<usize>,
+}
+
+/// Runs `world` for `steps` steps, making the scenario's changes and checks
+/// along the way if there is one, and measuring the metrics at every step.
+/// Calls `on_step` after each step; an error from it stops the simulation.
+/// Stops early if the particles stop being finite.
+pub fn simulate(
+    world: &mut CpuWorld,
+    rules: &mut Rules,
+    scenario: Option<&Scenario>,
+    steps: usize,
+    mut on_step: impl FnMut(&StepReport) -> Result<()>,
+) -> Result<Simulation> {
+    let world_size = world.world_size();
+    let mut simulation = Simulation {
+        history: Vec::with_capacity(steps),
+        checks: Vec::new(),
+        non_finite_step: None,
+    };
+
+    for step in 1..=steps {
+        if let Some(scenario) = scenario {
+            scenario.apply_changes(step - 1, world, rules);
+        }
+        world.step(rules);
+
+        let metrics = Metrics::measure(world.particles(), world_size);
+        simulation.history.push(metrics);
+
+        let finite = fitness::all_finite(world.particles());
+        let checks = match scenario {
+            Some(scenario) if finite => scenario.check(step, &simulation.history),
+            _ => Vec::new(),
+        };
+
+        let report = StepReport {
+            step,
+            world,
+            metrics,
+            finite,
+            checks,
+        };
+        on_step(&report)?;
+        simulation.checks.extend(report.checks);
+
+        if !finite {
+            simulation.non_finite_step = Some(step);
+            break;
+        }
+    }
+
+    Ok(simulation)
+}
+
+fn apply_settings(
+    settings: &mut ShaderGlobalConstants,
+    preset: Option<&Preset>,
+    friction: Option<f32>,
+    force_multiplier: Option<f32>,
+) {
+    if let Some(preset) = preset {
+        preset.apply_settings(settings);
+    }
+    if let Some(friction) = friction {
+        settings.friction = friction;
+    }
+    if let Some(force_multiplier) = force_multiplier {
+        settings.force_multiplier = force_multiplier;
+    }
+}
+
+impl Checkpoint {
+    fn from_file(file: CheckpointFile, steps: usize) -> Result<Self> {
+        let step = file.step;
+        ensure!(
+            (1..=steps).contains(&step),
+            "checkpoint at step {step} must be between 1 and the last step, {steps}"
+        );
+        ensure!(
+            (1..=step).contains(&file.window),
+            "checkpoint at step {step}: window must be between 1 and {step}, got {}",
+            file.window
+        );
+
+        let sample = Metrics::default();
+        for (name, bounds) in &file.metrics {
+            if sample.value(name).is_none() {
+                bail!(
+                    "checkpoint at step {step}: unknown metric {name:?}, expected one of {}",
+                    Metrics::csv_header()
+                );
+            }
+            ensure!(
+                bounds.min.is_some() || bounds.max.is_some(),
+                "checkpoint at step {step}: {name} needs a min or a max"
+            );
+        }
+
+        let behaviour = file
+            .behaviour
+            .iter()
+            .map(|name| {
+                Behaviour::from_name(name).with_context(|| {
+                    let names: Vec<_> = Behaviour::ALL.iter().map(|b| b.name()).collect();
+                    format!(
+                        "checkpoint at step {step}: unknown behaviour {name:?}, expected one of {}",
+                        names.join(", ")
+                    )
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        ensure!(
+            behaviour.is_empty() || step >= MIN_SAMPLES,
+            "checkpoint at step {step}: behaviour can't be judged before step {MIN_SAMPLES}"
+        );
+
+        Ok(Checkpoint {
+            step,
+            window: file.window,
+            metrics: file.metrics,
+            behaviour,
+        })
+    }
+
+    fn check(&self, history: &[Metrics]) -> Vec<Check> {
+        let step = self.step;
+        let mean = Metrics::mean(&history[history.len().saturating_sub(self.window)..]);
+
+        let mut checks: Vec<Check> = self
+            .metrics
+            .iter()
+            .map(|(name, bounds)| {
+                let value = mean.value(name).unwrap_or(f32::NAN);
+                let passed = value.is_finite()
+                    && bounds.min.is_none_or(|min| value >= min)
+                    && bounds.max.is_none_or(|max| value <= max);
+                let min = bounds
+                    .min
+                    .map_or_else(String::new, |min| format!("{min} <= "));
+                let max = bounds
+                    .max
+                    .map_or_else(String::new, |max| format!(" <= {max}"));
+                Check {
+                    description: format!("step {step}: {min}{name}{max}, got {value}"),
+                    passed,
+                }
+            })
+            .collect();
+
+        if !self.behaviour.is_empty() {
+            let window = &history[history.len().saturating_sub(CLASSIFICATION_WINDOW)..];
+            let names: Vec<_> = self.behaviour.iter().map(|b| b.name()).collect();
+            let (passed, got) = match classify(window) {
+                Some(classification) => (
+                    self.behaviour.contains(&classification.behaviour),
+                    classification.behaviour.name(),
+                ),
+                None => (false, "unknown"),
+            };
+            checks.push(Check {
+                description: format!(
+                    "step {step}: behaviour is {}, got {got}",
+                    names.join(" or ")
+                ),
+                passed,
+            });
+        }
+
+        checks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use super::{simulate, Scenario};
+
+    /// Runs every scenario in `scenarios/` on the CPU through `simulate`, as
+    /// `run --scenario` does, so that changes which break one are caught.
+    #[test]
+    fn scenarios_pass() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenarios");
+        let mut paths: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+            .collect();
+        paths.sort();
+        assert!(!paths.is_empty(), "no scenarios in {}", dir.display());
+
+        for path in paths {
+            let scenario =
+                Scenario::load(&path).unwrap_or_else(|e| panic!("{}: {e:#}", path.display()));
+            let (mut world, mut rules) = scenario.create_world();
+            let simulation = simulate(
+                &mut world,
+                &mut rules,
+                Some(&scenario),
+                scenario.steps,
+                |_| Ok(()),
+            )
+            .unwrap();
+
+            assert_eq!(
+                simulation.non_finite_step,
+                None,
+                "{} became non-finite",
+                path.display()
+            );
+            let failed: Vec<&str> = simulation
+                .checks
+                .iter()
+                .filter(|check| !check.passed)
+                .map(|check| check.description.as_str())
+                .collect();
+            assert!(
+                failed.is_empty(),
+                "{} failed: {}",
+                path.display(),
+                failed.join("; ")
+            );
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use anyhow::{ensure, Context, Result};
+use serde::{Deserialize, Serialize};
+use vek::Vec2;
+
+use super::{presets::Preset, ParticleKind};
+
+/// Everything needed to start a world again: how the particles are placed,
+/// and the rules and settings they move by.
+#[derive(Clone)]
+pub struct Scene {
+    /// Seed the particles were first placed with.
+    pub seed: u64,
+    pub particles: usize,
+    pub species: u32,
+    pub world_size: Vec2<f32>,
+    pub preset: Preset,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneFile {
+    /// A string, since TOML integers can't hold seeds above `i64::MAX`.
+    seed: String,
+    particles: usize,
+    species: u32,
+    world_size: [f32; 2],
+    /// Stored the same way as a preset file.
+    preset: toml::Table,
+}
+
+impl Scene {
+    pub fn to_toml(&self) -> Result<String> {
+        let file = SceneFile {
+            seed: self.seed.to_string(),
+            particles: self.particles,
+            species: self.species,
+            world_size: self.world_size.into_array(),
+            preset: toml::from_str(&self.preset.to_toml())?,
+        };
+
+        Ok(toml::to_string(&file)?)
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let file: SceneFile = toml::from_str(text)?;
+
+        let seed = file
+            .seed
+            .parse()
+            .with_context(|| format!("seed is not a whole number: {}", file.seed))?;
+
+        ensure!(file.particles > 0, "particles must be at least 1");
+        ensure!(
+            (1..=ParticleKind::MAX).contains(&file.species),
+            "species must be between 1 and {}, got {}",
+            ParticleKind::MAX,
+            file.species
+        );
+        ensure!(
+            file.world_size
+                .iter()
+                .all(|size| size.is_finite() && *size > 0.0),
+            "world size must be greater than zero, got {}x{}",
+            file.world_size[0],
+            file.world_size[1]
+        );
+
+        let preset = Preset::parse(&toml::to_string(&file.preset)?).context("parsing preset")?;
+
+        Ok(Scene {
+            seed,
+            particles: file.particles,
+            species: file.species,
+            world_size: Vec2::from(file.world_size),
+            preset,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use vek::Vec2;
+
+    use super::Scene;
+    use crate::particle_life::{presets::Preset, Rule, Rules, ShaderGlobalConstants};
+
+    #[test]
+    fn round_trip_with_a_large_seed() {
+        let settings = ShaderGlobalConstants::new(10, Vec2::new(100.0, 80.0));
+        let rules = Rules::from_fn(|_, _| Rule {
+            force: 0.5,
+            min_distance: 10.0,
+            max_distance: 50.0,
+        });
+        let scene = Scene {
+            seed: u64::MAX,
+            particles: 10,
+            species: 3,
+            world_size: Vec2::new(100.0, 80.0),
+            preset: Preset::new("Test", &settings, &rules),
+        };
+
+        let parsed = Scene::parse(&scene.to_toml().unwrap()).unwrap();
+
+        assert_eq!(parsed.seed, u64::MAX);
+        assert_eq!(parsed.particles, 10);
+        assert_eq!(parsed.species, 3);
+        assert_eq!(parsed.world_size, scene.world_size);
+    }
+}
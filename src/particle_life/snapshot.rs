@@ -0,0 +1,707 @@
+use std::{fmt::Write as _, fs, path::Path};
+
+use anyhow::{bail, ensure, Context, Result};
+use clap::ValueEnum;
+use vek::Vec2;
+
+use super::{Particle, ParticleKind};
+
+/// Radius, in world units, of the circles drawn for each particle in SVGs.
+const SVG_RADIUS: f32 = 2.0;
+
+/// Start of the PLY comment that holds the size of the world the particles
+/// came from.
+const PLY_WORLD_SIZE: &str = "world_size";
+
+#[derive(Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum SnapshotFormat {
+    /// One row per particle, with its ID, kind, position and velocity.
+    #[default]
+    Csv,
+    /// A picture of the world, with one circle per particle coloured by kind.
+    Svg,
+    /// An ASCII PLY point cloud, with velocity, kind and ID for each point.
+    Ply,
+    /// A legacy ASCII VTK point cloud, which most scientific viewers open.
+    Vtk,
+}
+
+impl SnapshotFormat {
+    pub const ALL: [SnapshotFormat; 4] = [
+        SnapshotFormat::Csv,
+        SnapshotFormat::Svg,
+        SnapshotFormat::Ply,
+        SnapshotFormat::Vtk,
+    ];
+
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            SnapshotFormat::Csv => "CSV",
+            SnapshotFormat::Svg => "SVG",
+            SnapshotFormat::Ply => "PLY",
+            SnapshotFormat::Vtk => "VTK",
+        }
+    }
+
+    #[must_use]
+    pub fn extension(self) -> &'static str {
+        match self {
+            SnapshotFormat::Csv => "csv",
+            SnapshotFormat::Svg => "svg",
+            SnapshotFormat::Ply => "ply",
+            SnapshotFormat::Vtk => "vtk",
+        }
+    }
+
+    /// The particles, in a world of the given size, written in this format.
+    #[must_use]
+    pub fn encode(self, particles: &[Particle], world_size: Vec2<f32>) -> String {
+        match self {
+            SnapshotFormat::Csv => to_csv(particles),
+            SnapshotFormat::Svg => to_svg(particles, world_size),
+            SnapshotFormat::Ply => to_ply(particles, world_size),
+            SnapshotFormat::Vtk => to_vtk(particles),
+        }
+    }
+}
+
+/// The particles as CSV, one row per particle.
+#[must_use]
+pub fn to_csv(particles: &[Particle]) -> String {
+    let mut csv = String::from("id,kind,x,y,velocity_x,velocity_y\n");
+
+    for particle in particles {
+        writeln!(
+            csv,
+            "{},{},{},{},{},{}",
+            particle.id,
+            particle.kind.index(),
+            particle.position.x,
+            particle.position.y,
+            particle.velocity.x,
+            particle.velocity.y
+        )
+        .unwrap();
+    }
+
+    csv
+}
+
+/// The whole world as an SVG, one circle per particle coloured by kind, laid
+/// out the same way as screenshots.
+#[must_use]
+pub fn to_svg(particles: &[Particle], world_size: Vec2<f32>) -> String {
+    let mut svg = String::new();
+    writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 {w} {h}" width="{w}" height="{h}">"#,
+        w = world_size.x,
+        h = world_size.y
+    )
+    .unwrap();
+
+    svg.push_str("<style>\n");
+    for kind in ParticleKind::all() {
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let [r, g, b] = {
+            let [r, g, b, _] = kind.as_rgba();
+            [r, g, b].map(|c| (c * 255.0).round() as u8)
+        };
+        writeln!(
+            svg,
+            ".k{} {{ fill: #{r:02x}{g:02x}{b:02x}; }}",
+            kind.index()
+        )
+        .unwrap();
+    }
+    svg.push_str("</style>\n");
+    svg.push_str(r#"<rect width="100%" height="100%" fill="black"/>"#);
+    svg.push('\n');
+
+    for particle in particles {
+        // Screenshots have y up, but SVGs have it down.
+        writeln!(
+            svg,
+            r#"<circle class="k{}" cx="{}" cy="{}" r="{SVG_RADIUS}"/>"#,
+            particle.kind.index(),
+            particle.position.x,
+            world_size.y - particle.position.y
+        )
+        .unwrap();
+    }
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// The particles as an ASCII PLY point cloud, at z = 0. The world size is kept
+/// in a comment so that `load` can place them the same way again.
+#[must_use]
+pub fn to_ply(particles: &[Particle], world_size: Vec2<f32>) -> String {
+    let mut ply = String::from("ply\nformat ascii 1.0\ncomment dplife particles\n");
+    writeln!(
+        ply,
+        "comment {PLY_WORLD_SIZE} {} {}",
+        world_size.x, world_size.y
+    )
+    .unwrap();
+    writeln!(ply, "element vertex {}", particles.len()).unwrap();
+    for property in [
+        "float x",
+        "float y",
+        "float z",
+        "float velocity_x",
+        "float velocity_y",
+        "uchar kind",
+        "uint id",
+    ] {
+        writeln!(ply, "property {property}").unwrap();
+    }
+    ply.push_str("end_header\n");
+
+    for particle in particles {
+        writeln!(
+            ply,
+            "{} {} 0 {} {} {} {}",
+            particle.position.x,
+            particle.position.y,
+            particle.velocity.x,
+            particle.velocity.y,
+            particle.kind.index(),
+            particle.id
+        )
+        .unwrap();
+    }
+
+    ply
+}
+
+/// The particles as a legacy ASCII VTK polydata file, at z = 0, with velocity
+/// as a vector and kind and ID as scalars.
+#[must_use]
+pub fn to_vtk(particles: &[Particle]) -> String {
+    let n = particles.len();
+    let mut vtk = String::from("# vtk DataFile Version 3.0\ndplife particles\nASCII\n");
+    vtk.push_str("DATASET POLYDATA\n");
+
+    writeln!(vtk, "POINTS {n} float").unwrap();
+    for particle in particles {
+        writeln!(vtk, "{} {} 0", particle.position.x, particle.position.y).unwrap();
+    }
+
+    // Without cells, ParaView has nothing to draw.
+    writeln!(vtk, "VERTICES {n} {}", n * 2).unwrap();
+    for i in 0..n {
+        writeln!(vtk, "1 {i}").unwrap();
+    }
+
+    writeln!(vtk, "POINT_DATA {n}").unwrap();
+    vtk.push_str("VECTORS velocity float\n");
+    for particle in particles {
+        writeln!(vtk, "{} {} 0", particle.velocity.x, particle.velocity.y).unwrap();
+    }
+    vtk.push_str("SCALARS kind int 1\nLOOKUP_TABLE default\n");
+    for particle in particles {
+        writeln!(vtk, "{}", particle.kind.index()).unwrap();
+    }
+    vtk.push_str("SCALARS id unsigned_int 1\nLOOKUP_TABLE default\n");
+    for particle in particles {
+        writeln!(vtk, "{}", particle.id).unwrap();
+    }
+
+    vtk
+}
+
+/// Particles read from a CSV or PLY file, such as those written by `to_csv`
+/// and `to_ply`, ready to be fitted into a world.
+pub struct Import {
+    records: Vec<Record>,
+    /// Size of the world the particles came from, if the file says.
+    source_size: Option<Vec2<f32>>,
+}
+
+impl Import {
+    /// Reads the particles in `path`, choosing the format by its extension.
+    ///
+    /// Each particle needs `x`, `y` and `kind`, and may have `velocity_x` and
+    /// `velocity_y`; anything else is ignored. Kinds must be below `species`.
+    pub fn load(path: &Path, species: u32) -> Result<Self> {
+        let text =
+            fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_ascii_lowercase);
+
+        let (records, source_size) = match extension.as_deref() {
+            Some("csv") => parse_csv(&text, species).map(|records| (records, None)),
+            Some("ply") => parse_ply(&text, species),
+            _ => bail!(
+                "{} is not a .csv or .ply file, so can't be imported",
+                path.display()
+            ),
+        }
+        .with_context(|| format!("parsing {}", path.display()))?;
+        ensure!(
+            !records.is_empty(),
+            "{} has no particles in it",
+            path.display()
+        );
+
+        Ok(Import {
+            records,
+            source_size,
+        })
+    }
+
+    #[must_use]
+    pub fn num_particles(&self) -> usize {
+        self.records.len()
+    }
+
+    /// The particles, scaled to a world of `world_size` from the world size in
+    /// a PLY's comments if it has one, or else from a box a little larger than
+    /// the smallest one that holds them all. Velocities are scaled along with
+    /// the positions, and any particles that end up outside are wrapped
+    /// around, as they would be by the simulation. Particles are numbered in
+    /// the order they were read.
+    #[must_use]
+    pub fn fit(&self, world_size: Vec2<f32>) -> Vec<Particle> {
+        let records = &self.records;
+        let (origin, size) = self.source_size.map_or_else(
+            || {
+                let min = records
+                    .iter()
+                    .fold(Vec2::broadcast(f32::INFINITY), |min, r| {
+                        Vec2::partial_min(min, r.position)
+                    });
+                let max = records
+                    .iter()
+                    .fold(Vec2::broadcast(f32::NEG_INFINITY), |max, r| {
+                        Vec2::partial_max(max, r.position)
+                    });
+                // The world wraps around, so the box is widened by about the
+                // gap between neighbouring particles to keep those on
+                // opposite edges from landing on top of each other.
+                #[allow(clippy::cast_precision_loss)]
+                let n = records.len() as f32;
+                let size = (max - min) * (n + 1.0) / (n - 1.0).max(1.0);
+                (min - (size - (max - min)) / 2.0, size)
+            },
+            |size| (Vec2::zero(), size),
+        );
+
+        // Particles all in a line along one axis are kept at the same scale
+        // along it.
+        let scale = Vec2::new(
+            if size.x > 0.0 {
+                world_size.x / size.x
+            } else {
+                1.0
+            },
+            if size.y > 0.0 {
+                world_size.y / size.y
+            } else {
+                1.0
+            },
+        );
+
+        records
+            .iter()
+            .zip(0..)
+            .map(|(record, id)| {
+                let position = (record.position - origin) * scale;
+                Particle {
+                    position: Vec2::new(
+                        position.x.rem_euclid(world_size.x),
+                        position.y.rem_euclid(world_size.y),
+                    ),
+                    velocity: record.velocity * scale,
+                    kind: ParticleKind(record.kind),
+                    neighbours: 0,
+                    id,
+                }
+            })
+            .collect()
+    }
+}
+
+/// A particle as read from a file, before it is fitted into the world.
+struct Record {
+    position: Vec2<f32>,
+    velocity: Vec2<f32>,
+    kind: u32,
+}
+
+/// Where each of a particle's values is among the fields of a row.
+struct Columns {
+    x: usize,
+    y: usize,
+    kind: usize,
+    velocity_x: Option<usize>,
+    velocity_y: Option<usize>,
+    len: usize,
+}
+
+impl Columns {
+    fn find(names: &[&str]) -> Result<Self> {
+        let find = |name: &str| names.iter().position(|n| *n == name);
+        let require = |name: &str| find(name).with_context(|| format!("no {name} column"));
+
+        Ok(Columns {
+            x: require("x")?,
+            y: require("y")?,
+            kind: require("kind")?,
+            velocity_x: find("velocity_x"),
+            velocity_y: find("velocity_y"),
+            len: names.len(),
+        })
+    }
+
+    fn record(&self, fields: &[&str], species: u32) -> Result<Record> {
+        ensure!(
+            fields.len() == self.len,
+            "expected {} values, got {}",
+            self.len,
+            fields.len()
+        );
+
+        let float = |index: usize, name: &str| -> Result<f32> {
+            fields[index]
+                .parse::<f32>()
+                .ok()
+                .filter(|value| value.is_finite())
+                .with_context(|| format!("{name} is not a finite number: {}", fields[index]))
+        };
+        let optional = |index: Option<usize>, name: &str| index.map_or(Ok(0.0), |i| float(i, name));
+
+        let kind: u32 = fields[self.kind]
+            .parse()
+            .with_context(|| format!("kind is not a whole number: {}", fields[self.kind]))?;
+        ensure!(
+            kind < species,
+            "kind {kind} is too large for {species} species"
+        );
+
+        Ok(Record {
+            position: Vec2::new(float(self.x, "x")?, float(self.y, "y")?),
+            velocity: Vec2::new(
+                optional(self.velocity_x, "velocity_x")?,
+                optional(self.velocity_y, "velocity_y")?,
+            ),
+            kind,
+        })
+    }
+}
+
+fn parse_csv(text: &str, species: u32) -> Result<Vec<Record>> {
+    let mut lines = text.lines().enumerate();
+    let (_, header) = lines.next().context("no header row")?;
+    let names: Vec<_> = header.split(',').map(str::trim).collect();
+    let columns = Columns::find(&names)?;
+
+    lines
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            let fields: Vec<_> = line.split(',').map(str::trim).collect();
+            columns
+                .record(&fields, species)
+                .with_context(|| format!("line {}", index + 1))
+        })
+        .collect()
+}
+
+/// Reads the vertices of an ASCII PLY file, along with the world size in its
+/// comments if there is one.
+fn parse_ply(text: &str, species: u32) -> Result<(Vec<Record>, Option<Vec2<f32>>)> {
+    let mut lines = text.lines().enumerate();
+    ensure!(
+        lines.next().is_some_and(|(_, line)| line.trim() == "ply"),
+        "not a PLY file"
+    );
+
+    let mut source_size = None;
+    let mut num_vertices = None;
+    // Whether the properties being read belong to the vertex element.
+    let mut in_vertex = false;
+    let mut names = Vec::new();
+    for (index, line) in lines.by_ref() {
+        let words: Vec<_> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["format", format, _] if *format != "ascii" => {
+                bail!("only ASCII PLY files can be imported, got {format}")
+            }
+            ["comment", PLY_WORLD_SIZE, width, height] => {
+                if let (Ok(width), Ok(height)) = (width.parse(), height.parse()) {
+                    source_size = Some(Vec2::new(width, height))
+                        .filter(|size: &Vec2<f32>| size.iter().all(|s| s.is_finite() && *s > 0.0));
+                }
+            }
+            ["element", "vertex", count] => {
+                ensure!(num_vertices.is_none(), "more than one vertex element");
+                num_vertices = Some(
+                    count
+                        .parse::<usize>()
+                        .with_context(|| format!("line {}: bad vertex count", index + 1))?,
+                );
+                in_vertex = true;
+            }
+            ["element", name, _] => {
+                ensure!(
+                    num_vertices.is_some(),
+                    "the vertex element must come before {name}"
+                );
+                in_vertex = false;
+            }
+            ["property", "list", ..] if in_vertex => {
+                bail!("line {}: vertices can't have list properties", index + 1)
+            }
+            ["property", .., name] if in_vertex => names.push(*name),
+            ["format" | "comment" | "obj_info" | "property", ..] | [] => {}
+            ["end_header"] => break,
+            _ => bail!("line {}: unexpected header line: {line}", index + 1),
+        }
+    }
+
+    let num_vertices = num_vertices.context("no vertex element")?;
+    let columns = Columns::find(&names)?;
+
+    let records = lines
+        .take(num_vertices)
+        .map(|(index, line)| {
+            let fields: Vec<_> = line.split_whitespace().collect();
+            columns
+                .record(&fields, species)
+                .with_context(|| format!("line {}", index + 1))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    ensure!(
+        records.len() == num_vertices,
+        "expected {num_vertices} vertices, got {}",
+        records.len()
+    );
+
+    Ok((records, source_size))
+}
+
+#[cfg(test)]
+mod tests {
+    use vek::Vec2;
+
+    use super::{parse_csv, parse_ply, to_csv, to_ply, to_svg, to_vtk, Import, Record, SVG_RADIUS};
+    use crate::particle_life::{Particle, ParticleKind};
+
+    fn particles() -> Vec<Particle> {
+        [(10.0, 20.0, 0), (35.5, 4.25, 3), (90.0, 70.0, 7)]
+            .into_iter()
+            .zip(0..)
+            .map(|((x, y, kind), id)| Particle {
+                position: Vec2::new(x, y),
+                velocity: Vec2::new(0.5, -1.5),
+                kind: ParticleKind(kind),
+                neighbours: 0,
+                id,
+            })
+            .collect()
+    }
+
+    fn assert_matches(records: &[Record], particles: &[Particle]) {
+        assert_eq!(records.len(), particles.len());
+        for (record, particle) in records.iter().zip(particles) {
+            assert_eq!(record.position, particle.position);
+            assert_eq!(record.velocity, particle.velocity);
+            assert_eq!(record.kind, particle.kind.0);
+        }
+    }
+
+    fn import(positions: &[Vec2<f32>]) -> Import {
+        Import {
+            records: positions
+                .iter()
+                .map(|position| Record {
+                    position: *position,
+                    velocity: Vec2::new(1.0, 1.0),
+                    kind: 0,
+                })
+                .collect(),
+            source_size: None,
+        }
+    }
+
+    #[test]
+    fn csv_round_trip() {
+        let particles = particles();
+        let records = parse_csv(&to_csv(&particles), ParticleKind::MAX).unwrap();
+
+        assert_matches(&records, &particles);
+    }
+
+    #[test]
+    fn ply_round_trip() {
+        let particles = particles();
+        let world_size = Vec2::new(100.0, 80.0);
+        let (records, source_size) =
+            parse_ply(&to_ply(&particles, world_size), ParticleKind::MAX).unwrap();
+
+        assert_matches(&records, &particles);
+        assert_eq!(source_size, Some(world_size));
+
+        // With the world size known, fitting into the same world changes
+        // nothing.
+        let fitted = Import {
+            records,
+            source_size,
+        }
+        .fit(world_size);
+        for (fitted, particle) in fitted.iter().zip(&particles) {
+            assert_eq!(fitted.position, particle.position);
+            assert_eq!(fitted.id, particle.id);
+        }
+    }
+
+    #[test]
+    fn svg_has_a_circle_per_particle() {
+        let svg = to_svg(&particles(), Vec2::new(100.0, 80.0));
+
+        assert!(svg.starts_with(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 100 80" width="100" height="80">"#
+        ));
+        assert!(svg.ends_with("</svg>\n"));
+        assert_eq!(svg.matches(".k").count(), ParticleKind::all().count());
+        assert_eq!(svg.matches("<circle ").count(), 3);
+        // Flipped so that y points down
+        assert!(svg.contains(&format!(
+            r#"<circle class="k3" cx="35.5" cy="75.75" r="{SVG_RADIUS}"/>"#
+        )));
+    }
+
+    #[test]
+    fn vtk_has_points_cells_and_data() {
+        let vtk = to_vtk(&particles());
+        let lines: Vec<_> = vtk.lines().collect();
+
+        assert_eq!(
+            lines[..5],
+            [
+                "# vtk DataFile Version 3.0",
+                "dplife particles",
+                "ASCII",
+                "DATASET POLYDATA",
+                "POINTS 3 float"
+            ]
+        );
+        assert_eq!(lines[6], "35.5 4.25 0");
+        assert_eq!(lines[8..12], ["VERTICES 3 6", "1 0", "1 1", "1 2"]);
+        assert_eq!(lines[12..14], ["POINT_DATA 3", "VECTORS velocity float"]);
+        assert_eq!(lines[15], "0.5 -1.5 0");
+        assert_eq!(
+            lines[17..19],
+            ["SCALARS kind int 1", "LOOKUP_TABLE default"]
+        );
+        assert_eq!(lines[20], "3");
+        assert_eq!(
+            lines[22..24],
+            ["SCALARS id unsigned_int 1", "LOOKUP_TABLE default"]
+        );
+        assert_eq!(lines[25], "1");
+        assert_eq!(lines.len(), 27);
+    }
+
+    #[test]
+    fn ascii_ply_from_other_tools() {
+        // Other properties and elements are ignored, and velocity is optional.
+        let ply = "ply
+format ascii 1.0
+comment made elsewhere
+element vertex 2
+property float x
+property float y
+property float z
+property uchar kind
+property uchar red
+element face 0
+property list uchar int vertex_indices
+end_header
+1 2 0 3 255
+4 5 0 1 0
+";
+        let (records, source_size) = parse_ply(ply, ParticleKind::MAX).unwrap();
+
+        assert!(source_size.is_none());
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].position, Vec2::new(1.0, 2.0));
+        assert_eq!(records[0].velocity, Vec2::zero());
+        assert_eq!(records[0].kind, 3);
+        assert_eq!(records[1].position, Vec2::new(4.0, 5.0));
+        assert_eq!(records[1].kind, 1);
+    }
+
+    #[test]
+    fn binary_ply_is_rejected() {
+        let ply = "ply
+format binary_little_endian 1.0
+element vertex 1
+property float x
+property float y
+property uchar kind
+end_header
+";
+        let error = parse_ply(ply, ParticleKind::MAX).err().unwrap();
+
+        assert!(format!("{error:#}").contains("only ASCII PLY files"));
+    }
+
+    #[test]
+    fn kind_out_of_range_is_rejected() {
+        let csv = "x,y,kind\n1,2,0\n3,4,2\n";
+        let error = parse_csv(csv, 2).err().unwrap();
+
+        assert!(format!("{error:#}").contains("line 3: kind 2 is too large for 2 species"));
+    }
+
+    #[test]
+    fn vertex_count_mismatch_is_rejected() {
+        let ply = "ply
+format ascii 1.0
+element vertex 3
+property float x
+property float y
+property uchar kind
+end_header
+1 2 0
+3 4 0
+";
+        let error = parse_ply(ply, ParticleKind::MAX).err().unwrap();
+
+        assert!(format!("{error:#}").contains("expected 3 vertices, got 2"));
+    }
+
+    #[test]
+    fn fit_one_particle() {
+        let world_size = Vec2::new(100.0, 80.0);
+        let fitted = import(&[Vec2::new(-500.0, 1e6)]).fit(world_size);
+
+        assert_eq!(fitted.len(), 1);
+        let particle = &fitted[0];
+        assert!(particle.position.x >= 0.0 && particle.position.x < world_size.x);
+        assert!(particle.position.y >= 0.0 && particle.position.y < world_size.y);
+        assert_eq!(particle.velocity, Vec2::new(1.0, 1.0));
+    }
+
+    #[test]
+    fn fit_particles_at_the_same_point() {
+        let world_size = Vec2::new(100.0, 80.0);
+        let fitted = import(&[Vec2::new(3.0, 3.0); 4]).fit(world_size);
+
+        assert_eq!(fitted.len(), 4);
+        for particle in &fitted {
+            assert_eq!(particle.position, fitted[0].position);
+            assert!(particle.position.x >= 0.0 && particle.position.x < world_size.x);
+            assert!(particle.position.y >= 0.0 && particle.position.y < world_size.y);
+            assert!(particle.velocity.iter().all(|v| v.is_finite()));
+        }
+        let ids: Vec<_> = fitted.iter().map(|particle| particle.id).collect();
+        assert_eq!(ids, [0, 1, 2, 3]);
+    }
+}
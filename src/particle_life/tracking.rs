@@ -0,0 +1,510 @@
+use std::{collections::HashMap, fmt::Write as _, fs, path::Path};
+
+use anyhow::Result;
+use vek::Vec2;
+
+use super::{clusters::Clustering, cpu::wrapped_delta, Particle, ParticleKind};
+
+const NUM_KINDS: usize = ParticleKind::MAX as usize;
+
+/// A cluster continues an organism from the previous snapshot if at least this
+/// fraction of the smaller of the two is made of the same particles.
+const MATCH_FRACTION: f32 = 0.5;
+
+#[derive(Clone)]
+pub struct TrackingParameters {
+    /// Clusters smaller than this aren't tracked.
+    pub min_size: usize,
+}
+
+impl Default for TrackingParameters {
+    fn default() -> Self {
+        TrackingParameters { min_size: 10 }
+    }
+}
+
+/// One observation of an organism.
+#[derive(Clone, Copy)]
+pub struct TrackSample {
+    pub step: u64,
+    pub centre: Vec2<f32>,
+    pub size: usize,
+    /// Movement of the centre per step since the previous sample.
+    pub velocity: Vec2<f32>,
+    /// Rotation about the centre, in radians per step, since the previous
+    /// sample. Positive is counter-clockwise, since y points up in the world.
+    pub angular_velocity: f32,
+    pub composition: [usize; NUM_KINDS],
+}
+
+/// The history of one organism: a cluster followed from snapshot to snapshot.
+pub struct Track {
+    pub id: usize,
+    pub samples: Vec<TrackSample>,
+}
+
+impl Track {
+    #[must_use]
+    pub fn first_step(&self) -> u64 {
+        self.samples[0].step
+    }
+
+    #[must_use]
+    pub fn last(&self) -> &TrackSample {
+        self.samples.last().unwrap()
+    }
+
+    #[must_use]
+    pub fn lifetime(&self) -> u64 {
+        self.last().step - self.first_step()
+    }
+}
+
+/// Where each member particle was, relative to the centre, when an organism
+/// was last seen.
+struct Members {
+    offsets: HashMap<usize, Vec2<f32>>,
+}
+
+pub struct TrackingSummary {
+    pub alive: usize,
+    /// Mean age, in steps, of the organisms that are alive.
+    pub mean_age: f32,
+    pub ended: usize,
+    /// Mean lifetime, in steps, of the organisms that have broken up.
+    pub mean_lifetime: f32,
+}
+
+/// Matches clusters between snapshots so that organisms keep the same ID while
+/// they live, and records how each one moves and changes.
+#[derive(Default)]
+pub struct OrganismTracker {
+    pub params: TrackingParameters,
+    next_id: usize,
+    alive: Vec<(Track, Members)>,
+    ended: Vec<Track>,
+}
+
+impl OrganismTracker {
+    /// Adds a snapshot, taken at `step`, and the clusters found in it.
+    /// Particles are matched by index, so the snapshots must come from the
+    /// same world, one step after another; call `end_all` when the particles
+    /// are replaced.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn update(
+        &mut self,
+        step: u64,
+        particles: &[Particle],
+        clustering: &Clustering,
+        world_size: Vec2<f32>,
+    ) {
+        let candidates: Vec<usize> = (0..clustering.clusters.len())
+            .filter(|c| clustering.clusters[*c].size() >= self.params.min_size)
+            .collect();
+
+        // Count the particles each cluster shares with each living organism,
+        // then match the biggest overlaps first so that when an organism
+        // splits, its largest piece keeps its ID.
+        let mut overlaps: Vec<(usize, usize, usize)> = Vec::new();
+        for (organism, (_, members)) in self.alive.iter().enumerate() {
+            let mut shared: HashMap<usize, usize> = HashMap::new();
+            for index in members.offsets.keys() {
+                if let Some(cluster) = clustering.labels[*index] {
+                    *shared.entry(cluster).or_default() += 1;
+                }
+            }
+            for (cluster, count) in shared {
+                if clustering.clusters[cluster].size() >= self.params.min_size {
+                    overlaps.push((count, organism, cluster));
+                }
+            }
+        }
+        overlaps.sort_by(|a, b| b.cmp(a));
+
+        let mut matched_organism = vec![None; clustering.clusters.len()];
+        let mut organism_taken = vec![false; self.alive.len()];
+        for (count, organism, cluster) in overlaps {
+            if organism_taken[organism] || matched_organism[cluster].is_some() {
+                continue;
+            }
+
+            let smaller = self.alive[organism]
+                .1
+                .offsets
+                .len()
+                .min(clustering.clusters[cluster].size());
+            if count as f32 >= smaller as f32 * MATCH_FRACTION {
+                organism_taken[organism] = true;
+                matched_organism[cluster] = Some(organism);
+            }
+        }
+
+        let mut previous: Vec<Option<(Track, Members)>> = self.alive.drain(..).map(Some).collect();
+        for (organism, taken) in organism_taken.iter().enumerate() {
+            if !taken {
+                let (track, _) = previous[organism].take().unwrap();
+                self.ended.push(track);
+            }
+        }
+
+        for cluster in candidates {
+            let members = &clustering.clusters[cluster].particles;
+
+            let (mut track, old_members) = if let Some(organism) = matched_organism[cluster] {
+                let (track, old_members) = previous[organism].take().unwrap();
+                (track, Some(old_members))
+            } else {
+                self.next_id += 1;
+                let track = Track {
+                    id: self.next_id,
+                    samples: Vec::new(),
+                };
+                (track, None)
+            };
+
+            let reference = track
+                .samples
+                .last()
+                .map_or(particles[members[0]].position, |sample| sample.centre);
+            let centre = wrapped_centre(particles, members, reference, world_size);
+
+            let offsets: HashMap<usize, Vec2<f32>> = members
+                .iter()
+                .map(|index| {
+                    let offset = wrapped_delta(centre, particles[*index].position, world_size);
+                    (*index, offset)
+                })
+                .collect();
+
+            let (velocity, angular_velocity) = match (track.samples.last(), &old_members) {
+                (Some(last), Some(old_members)) if step > last.step => {
+                    let steps = (step - last.step) as f32;
+                    let velocity = wrapped_delta(last.centre, centre, world_size) / steps;
+                    let rotation = rotation(&old_members.offsets, &offsets);
+                    (velocity, rotation / steps)
+                }
+                _ => (Vec2::zero(), 0.0),
+            };
+
+            track.samples.push(TrackSample {
+                step,
+                centre,
+                size: members.len(),
+                velocity,
+                angular_velocity,
+                composition: clustering.clusters[cluster].composition,
+            });
+            self.alive.push((track, Members { offsets }));
+        }
+    }
+
+    /// Ends every living organism, for when the particles they were made of
+    /// have been replaced.
+    pub fn end_all(&mut self) {
+        self.ended
+            .extend(self.alive.drain(..).map(|(track, _)| track));
+    }
+
+    pub fn alive(&self) -> impl Iterator<Item = &Track> {
+        self.alive.iter().map(|(track, _)| track)
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    pub fn summary(&self, step: u64) -> TrackingSummary {
+        let mean = |values: &[u64]| {
+            if values.is_empty() {
+                0.0
+            } else {
+                values.iter().sum::<u64>() as f32 / values.len() as f32
+            }
+        };
+
+        let ages: Vec<u64> = self
+            .alive()
+            .map(|track| step.saturating_sub(track.first_step()))
+            .collect();
+        let lifetimes: Vec<u64> = self.ended.iter().map(Track::lifetime).collect();
+
+        TrackingSummary {
+            alive: ages.len(),
+            mean_age: mean(&ages),
+            ended: lifetimes.len(),
+            mean_lifetime: mean(&lifetimes),
+        }
+    }
+
+    /// Every sample of every organism, living or not, as CSV.
+    #[must_use]
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("organism,step,x,y,size,velocity_x,velocity_y,angular_velocity");
+        for kind in ParticleKind::all() {
+            write!(csv, ",kind_{}", kind.index()).unwrap();
+        }
+        csv.push('\n');
+
+        let mut tracks: Vec<&Track> = self.ended.iter().chain(self.alive()).collect();
+        tracks.sort_by_key(|track| track.id);
+
+        for track in tracks {
+            for sample in &track.samples {
+                write!(
+                    csv,
+                    "{},{},{},{},{},{},{},{}",
+                    track.id,
+                    sample.step,
+                    sample.centre.x,
+                    sample.centre.y,
+                    sample.size,
+                    sample.velocity.x,
+                    sample.velocity.y,
+                    sample.angular_velocity
+                )
+                .unwrap();
+                for count in sample.composition {
+                    write!(csv, ",{count}").unwrap();
+                }
+                csv.push('\n');
+            }
+        }
+
+        csv
+    }
+
+    pub fn export(&self, path: &Path) -> Result<()> {
+        fs::write(path, self.to_csv())?;
+        Ok(())
+    }
+}
+
+/// The centre of a group of particles, measured from `reference` so that a
+/// group straddling the edge of the world isn't split in two. `reference`
+/// should be somewhere in or near the group.
+#[allow(clippy::cast_precision_loss)]
+fn wrapped_centre(
+    particles: &[Particle],
+    members: &[usize],
+    reference: Vec2<f32>,
+    world_size: Vec2<f32>,
+) -> Vec2<f32> {
+    let total: Vec2<f32> = members
+        .iter()
+        .map(|index| wrapped_delta(reference, particles[*index].position, world_size))
+        .sum();
+    let centre = reference + total / members.len() as f32;
+
+    Vec2::new(
+        centre.x.rem_euclid(world_size.x),
+        centre.y.rem_euclid(world_size.y),
+    )
+}
+
+/// The rotation that best maps the old offsets of the particles present in
+/// both snapshots onto their new offsets.
+fn rotation(old: &HashMap<usize, Vec2<f32>>, new: &HashMap<usize, Vec2<f32>>) -> f32 {
+    let (mut cross, mut dot) = (0.0, 0.0);
+
+    for (index, before) in old {
+        if let Some(after) = new.get(index) {
+            cross += before.x * after.y - before.y * after.x;
+            dot += before.dot(*after);
+        }
+    }
+
+    f32::atan2(cross, dot)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::TAU;
+
+    use vek::Vec2;
+
+    use super::{OrganismTracker, TrackingParameters};
+    use crate::particle_life::{
+        clusters::{find_clusters, ClusterParameters},
+        Particle, ParticleKind,
+    };
+
+    const WORLD_SIZE: Vec2<f32> = Vec2::new(100.0, 80.0);
+
+    const CLUSTERS: ClusterParameters = ClusterParameters {
+        radius: 5.0,
+        min_points: 3,
+    };
+
+    fn tracker() -> OrganismTracker {
+        OrganismTracker {
+            params: TrackingParameters { min_size: 3 },
+            ..Default::default()
+        }
+    }
+
+    /// `count` particles evenly spaced on a small circle around `centre`,
+    /// turned by `angle` radians.
+    #[allow(clippy::cast_precision_loss)]
+    fn blob(centre: Vec2<f32>, count: usize, angle: f32) -> Vec<Vec2<f32>> {
+        (0..count)
+            .map(|i| {
+                let theta = angle + TAU * i as f32 / count as f32;
+                centre + Vec2::new(theta.cos(), theta.sin()) * 2.0
+            })
+            .collect()
+    }
+
+    fn particles(positions: &[Vec2<f32>]) -> Vec<Particle> {
+        positions
+            .iter()
+            .zip(0..)
+            .map(|(position, id)| Particle {
+                position: *position,
+                velocity: Vec2::zero(),
+                kind: ParticleKind(id % 2),
+                neighbours: 0,
+                id,
+            })
+            .collect()
+    }
+
+    fn update(tracker: &mut OrganismTracker, step: u64, positions: &[Vec2<f32>]) {
+        let particles = particles(positions);
+        let clustering = find_clusters(&particles, WORLD_SIZE, &CLUSTERS);
+        tracker.update(step, &particles, &clustering, WORLD_SIZE);
+    }
+
+    /// The IDs of the living organisms, and the centre of each, by ID.
+    fn alive(tracker: &OrganismTracker) -> Vec<(usize, Vec2<f32>)> {
+        let mut alive: Vec<(usize, Vec2<f32>)> = tracker
+            .alive()
+            .map(|track| (track.id, track.last().centre))
+            .collect();
+        alive.sort_by_key(|(id, _)| *id);
+        alive
+    }
+
+    #[test]
+    fn organisms_keep_their_ids_as_they_move() {
+        let mut tracker = tracker();
+        let start = [
+            blob(Vec2::new(20.0, 20.0), 6, 0.0),
+            blob(Vec2::new(70.0, 3.0), 8, 0.0),
+        ]
+        .concat();
+        update(&mut tracker, 0, &start);
+        let ids: Vec<usize> = alive(&tracker).iter().map(|(id, _)| *id).collect();
+        assert_eq!(ids.len(), 2);
+
+        // The first moves right and turns counter-clockwise, the second moves
+        // down across the bottom edge of the world.
+        let moved = [
+            blob(Vec2::new(25.0, 20.0), 6, 0.1),
+            blob(Vec2::new(70.0, 78.0), 8, 0.0),
+        ]
+        .concat();
+        update(&mut tracker, 10, &moved);
+
+        let alive = alive(&tracker);
+        assert_eq!(alive.iter().map(|(id, _)| *id).collect::<Vec<_>>(), ids);
+
+        let first = tracker
+            .alive()
+            .find(|track| track.last().size == 6)
+            .unwrap();
+        assert_eq!(first.samples.len(), 2);
+        let sample = first.last();
+        assert!((sample.velocity - Vec2::new(0.5, 0.0)).magnitude() < 1e-4);
+        assert!((sample.angular_velocity - 0.01).abs() < 1e-4);
+
+        let second = tracker
+            .alive()
+            .find(|track| track.last().size == 8)
+            .unwrap();
+        let sample = second.last();
+        assert!((sample.velocity - Vec2::new(0.0, -0.5)).magnitude() < 1e-4);
+        assert!((sample.centre - Vec2::new(70.0, 78.0)).magnitude() < 1e-4);
+        assert_eq!(sample.composition[..2], [4, 4]);
+    }
+
+    #[test]
+    fn largest_piece_keeps_the_id_when_an_organism_splits() {
+        let mut tracker = tracker();
+        let whole = [
+            blob(Vec2::new(20.0, 20.0), 6, 0.0),
+            blob(Vec2::new(23.0, 20.0), 4, 0.0),
+        ]
+        .concat();
+        update(&mut tracker, 0, &whole);
+        let alive_before = alive(&tracker);
+        assert_eq!(alive_before.len(), 1);
+        let id = alive_before[0].0;
+
+        let split = [
+            blob(Vec2::new(20.0, 20.0), 6, 0.0),
+            blob(Vec2::new(60.0, 60.0), 4, 0.0),
+        ]
+        .concat();
+        update(&mut tracker, 10, &split);
+
+        let alive = alive(&tracker);
+        assert_eq!(alive.len(), 2);
+        let kept = tracker.alive().find(|track| track.id == id).unwrap();
+        assert_eq!(kept.last().size, 6);
+        assert_eq!(kept.samples.len(), 2);
+        let piece = tracker.alive().find(|track| track.id != id).unwrap();
+        assert_eq!(piece.last().size, 4);
+        assert_eq!(piece.samples.len(), 1);
+        assert_eq!(tracker.summary(10).ended, 0);
+    }
+
+    #[test]
+    fn end_all_starts_over_with_new_ids() {
+        let mut tracker = tracker();
+        let positions = blob(Vec2::new(20.0, 20.0), 6, 0.0);
+        update(&mut tracker, 0, &positions);
+        update(&mut tracker, 10, &positions);
+        let id = alive(&tracker)[0].0;
+
+        tracker.end_all();
+        assert_eq!(tracker.alive().count(), 0);
+        let summary = tracker.summary(10);
+        assert_eq!(summary.alive, 0);
+        assert_eq!(summary.ended, 1);
+        assert!((summary.mean_lifetime - 10.0).abs() < 1e-6);
+
+        // The same particles at the same place are a new organism
+        update(&mut tracker, 20, &positions);
+        let alive = alive(&tracker);
+        assert_eq!(alive.len(), 1);
+        assert_ne!(alive[0].0, id);
+    }
+
+    #[test]
+    fn csv_has_a_row_per_sample() {
+        let mut tracker = tracker();
+        let positions = blob(Vec2::new(20.0, 20.0), 6, 0.0);
+        update(&mut tracker, 0, &positions);
+        update(&mut tracker, 10, &positions);
+        tracker.end_all();
+        update(&mut tracker, 20, &blob(Vec2::new(50.0, 40.0), 4, 0.0));
+
+        let csv = tracker.to_csv();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(
+            lines[0],
+            "organism,step,x,y,size,velocity_x,velocity_y,angular_velocity,\
+             kind_0,kind_1,kind_2,kind_3,kind_4,kind_5,kind_6,kind_7"
+        );
+        assert_eq!(lines.len(), 4);
+
+        // Sorted by organism, then step
+        let keys: Vec<(&str, &str, &str)> = lines[1..]
+            .iter()
+            .map(|line| {
+                let fields: Vec<&str> = line.split(',').collect();
+                assert_eq!(fields.len(), 16);
+                (fields[0], fields[1], fields[4])
+            })
+            .collect();
+        assert_eq!(keys, [("1", "0", "6"), ("1", "10", "6"), ("2", "20", "4")]);
+        assert!(lines[3].ends_with(",2,2,0,0,0,0,0,0"));
+    }
+}
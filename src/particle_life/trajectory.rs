@@ -0,0 +1,369 @@
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    fs,
+    io::{self, Write},
+    path::Path,
+};
+
+use anyhow::Result;
+use clap::ValueEnum;
+use vek::Vec2;
+
+use super::{cpu::wrapped_delta, Particle, ParticleKind};
+
+/// Identifies the binary trajectory format, including its version.
+const BINARY_MAGIC: &[u8; 8] = b"DPLTRAJ1";
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum TrajectoryFormat {
+    /// One row per particle per frame, with its ID, kind and position.
+    Csv,
+    /// The compact format written by `TrajectoryRecorder::write_binary`.
+    Binary,
+}
+
+impl TrajectoryFormat {
+    #[must_use]
+    pub fn extension(self) -> &'static str {
+        match self {
+            TrajectoryFormat::Csv => "csv",
+            TrajectoryFormat::Binary => "bin",
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct TrajectoryParameters {
+    /// A frame is recorded every this many steps.
+    pub interval: u64,
+    /// Record at most this many particles, spread evenly over the particle
+    /// IDs, or every particle if `None`.
+    pub max_particles: Option<usize>,
+}
+
+impl Default for TrajectoryParameters {
+    fn default() -> Self {
+        TrajectoryParameters {
+            interval: 10,
+            max_particles: Some(1000),
+        }
+    }
+}
+
+/// Records the positions of a fixed set of particles every few steps.
+///
+/// Positions are unwrapped: when a particle crosses an edge of the world its
+/// recorded position carries on past the edge instead of jumping to the other
+/// side. This assumes no particle moves more than half the world between
+/// frames.
+pub struct TrajectoryRecorder {
+    params: TrajectoryParameters,
+    world_size: Vec2<f32>,
+    ids: Vec<u32>,
+    kinds: Vec<ParticleKind>,
+    steps: Vec<u64>,
+    /// Unwrapped positions, one per recorded particle for each frame.
+    positions: Vec<Vec2<f32>>,
+    /// Where each recorded particle was in the world in the last frame.
+    last_wrapped: Vec<Vec2<f32>>,
+    interrupted: bool,
+}
+
+impl TrajectoryRecorder {
+    /// Starts recording the particles in a snapshot taken at `step`, which
+    /// becomes the first frame.
+    #[must_use]
+    pub fn new(
+        params: TrajectoryParameters,
+        step: u64,
+        particles: &[Particle],
+        world_size: Vec2<f32>,
+    ) -> Self {
+        let mut selected: Vec<&Particle> = particles.iter().collect();
+        selected.sort_by_key(|particle| particle.id);
+        if let Some(max_particles) = params.max_particles {
+            let stride = selected.len().div_ceil(max_particles.max(1)).max(1);
+            selected = selected.into_iter().step_by(stride).collect();
+        }
+
+        let positions: Vec<Vec2<f32>> = selected.iter().map(|p| p.position).collect();
+        TrajectoryRecorder {
+            params,
+            world_size,
+            ids: selected.iter().map(|p| p.id).collect(),
+            kinds: selected.iter().map(|p| p.kind).collect(),
+            steps: vec![step],
+            last_wrapped: positions.clone(),
+            positions,
+            interrupted: false,
+        }
+    }
+
+    /// Adds a snapshot taken at `step` as a new frame if at least `interval`
+    /// steps have passed since the last one. Recording stops for good if the
+    /// world has been reset or the recorded particles are missing.
+    pub fn record(&mut self, step: u64, particles: &[Particle]) {
+        if self.interrupted {
+            return;
+        }
+
+        let last_step = *self.steps.last().unwrap();
+        if step < last_step {
+            self.interrupted = true;
+            return;
+        }
+        if step - last_step < self.params.interval {
+            return;
+        }
+
+        let slots: HashMap<u32, usize> = self
+            .ids
+            .iter()
+            .enumerate()
+            .map(|(slot, id)| (*id, slot))
+            .collect();
+        let mut wrapped = vec![None; self.ids.len()];
+        for particle in particles {
+            if let Some(slot) = slots.get(&particle.id) {
+                wrapped[*slot] = Some(particle.position);
+            }
+        }
+        let Some(wrapped) = wrapped.into_iter().collect::<Option<Vec<_>>>() else {
+            self.interrupted = true;
+            return;
+        };
+
+        let previous = self.positions.len() - self.ids.len();
+        for (slot, position) in wrapped.iter().enumerate() {
+            let delta = wrapped_delta(self.last_wrapped[slot], *position, self.world_size);
+            self.positions.push(self.positions[previous + slot] + delta);
+        }
+        self.last_wrapped = wrapped;
+        self.steps.push(step);
+    }
+
+    #[must_use]
+    pub fn num_particles(&self) -> usize {
+        self.ids.len()
+    }
+
+    #[must_use]
+    pub fn num_frames(&self) -> usize {
+        self.steps.len()
+    }
+
+    /// Whether recording stopped early because the world was reset.
+    #[must_use]
+    pub fn is_interrupted(&self) -> bool {
+        self.interrupted
+    }
+
+    fn frames(&self) -> impl Iterator<Item = (u64, &[Vec2<f32>])> {
+        self.steps
+            .iter()
+            .copied()
+            .zip(self.positions.chunks(self.ids.len().max(1)))
+    }
+
+    /// One row per particle per frame.
+    #[must_use]
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("step,id,kind,x,y\n");
+
+        for (step, positions) in self.frames() {
+            for ((id, kind), position) in self.ids.iter().zip(&self.kinds).zip(positions) {
+                writeln!(
+                    csv,
+                    "{step},{id},{},{},{}",
+                    kind.index(),
+                    position.x,
+                    position.y
+                )
+                .unwrap();
+            }
+        }
+
+        csv
+    }
+
+    /// Writes the trajectories in a compact binary format. All values are
+    /// little-endian:
+    ///
+    /// - the 8 bytes `DPLTRAJ1`
+    /// - number of particles `n` and number of frames, as `u32`s
+    /// - world width and height, as `f32`s
+    /// - `n` particle IDs, as `u32`s
+    /// - `n` particle kinds, as `u8`s
+    /// - for each frame, its step as a `u64`, then the x and y of each
+    ///   particle as `f32`s, in the same order as the IDs
+    pub fn write_binary(&self, writer: &mut impl Write) -> io::Result<()> {
+        let count = |n: usize| u32::try_from(n).unwrap().to_le_bytes();
+
+        writer.write_all(BINARY_MAGIC)?;
+        writer.write_all(&count(self.ids.len()))?;
+        writer.write_all(&count(self.steps.len()))?;
+        writer.write_all(&self.world_size.x.to_le_bytes())?;
+        writer.write_all(&self.world_size.y.to_le_bytes())?;
+        for id in &self.ids {
+            writer.write_all(&id.to_le_bytes())?;
+        }
+        for kind in &self.kinds {
+            writer.write_all(&[u8::try_from(kind.index()).unwrap()])?;
+        }
+
+        for (step, positions) in self.frames() {
+            writer.write_all(&step.to_le_bytes())?;
+            for position in positions {
+                writer.write_all(&position.x.to_le_bytes())?;
+                writer.write_all(&position.y.to_le_bytes())?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn export_csv(&self, path: &Path) -> Result<()> {
+        fs::write(path, self.to_csv())?;
+        Ok(())
+    }
+
+    pub fn export_binary(&self, path: &Path) -> Result<()> {
+        let mut writer = io::BufWriter::new(fs::File::create(path)?);
+        self.write_binary(&mut writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn export(&self, path: &Path, format: TrajectoryFormat) -> Result<()> {
+        match format {
+            TrajectoryFormat::Csv => self.export_csv(path),
+            TrajectoryFormat::Binary => self.export_binary(path),
+        }
+    }
+}
+
+#[cfg(test)]
+// Positions are only ever added to or copied, so exact comparisons are
+// intended.
+#[allow(clippy::float_cmp)]
+mod tests {
+    use vek::Vec2;
+
+    use super::{TrajectoryParameters, TrajectoryRecorder, BINARY_MAGIC};
+    use crate::particle_life::{Particle, ParticleKind};
+
+    const WORLD_SIZE: Vec2<f32> = Vec2::new(100.0, 80.0);
+
+    const PARAMS: TrajectoryParameters = TrajectoryParameters {
+        interval: 10,
+        max_particles: None,
+    };
+
+    fn particles(positions: &[(f32, f32)]) -> Vec<Particle> {
+        positions
+            .iter()
+            .zip(0..)
+            .map(|(&(x, y), id)| Particle {
+                position: Vec2::new(x, y),
+                velocity: Vec2::zero(),
+                kind: ParticleKind(id % 2 + 3),
+                neighbours: 0,
+                id,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn positions_carry_on_across_the_world_edge() {
+        let mut recorder = TrajectoryRecorder::new(
+            PARAMS,
+            0,
+            &particles(&[(98.0, 40.0), (50.0, 1.0)]),
+            WORLD_SIZE,
+        );
+
+        // Rightwards across the right edge, and downwards across the bottom
+        recorder.record(10, &particles(&[(1.0, 40.0), (50.0, 79.0)]));
+        // Onwards, and then back across the same edges
+        recorder.record(20, &particles(&[(4.0, 41.0), (50.0, 77.0)]));
+        recorder.record(30, &particles(&[(99.0, 41.0), (50.0, 2.0)]));
+
+        assert_eq!(recorder.num_frames(), 4);
+        let frames: Vec<(u64, Vec<Vec2<f32>>)> = recorder
+            .frames()
+            .map(|(step, positions)| (step, positions.to_vec()))
+            .collect();
+        assert_eq!(
+            frames,
+            [
+                (0, vec![Vec2::new(98.0, 40.0), Vec2::new(50.0, 1.0)]),
+                (10, vec![Vec2::new(101.0, 40.0), Vec2::new(50.0, -1.0)]),
+                (20, vec![Vec2::new(104.0, 41.0), Vec2::new(50.0, -3.0)]),
+                (30, vec![Vec2::new(99.0, 41.0), Vec2::new(50.0, 2.0)]),
+            ]
+        );
+    }
+
+    #[test]
+    fn frames_follow_the_interval() {
+        let start = particles(&[(10.0, 10.0)]);
+        let mut recorder = TrajectoryRecorder::new(PARAMS, 0, &start, WORLD_SIZE);
+
+        recorder.record(5, &start);
+        recorder.record(10, &start);
+        recorder.record(15, &start);
+        assert_eq!(recorder.num_frames(), 2);
+
+        // Going back in time means the world was reset
+        recorder.record(3, &start);
+        assert!(recorder.is_interrupted());
+        recorder.record(40, &start);
+        assert_eq!(recorder.num_frames(), 2);
+    }
+
+    #[test]
+    fn binary_layout() {
+        let mut recorder =
+            TrajectoryRecorder::new(PARAMS, 5, &particles(&[(1.5, 2.5), (3.0, 4.0)]), WORLD_SIZE);
+        recorder.record(15, &particles(&[(2.0, 3.0), (3.5, 4.5)]));
+
+        let mut bytes = Vec::new();
+        recorder.write_binary(&mut bytes).unwrap();
+
+        let mut expected = BINARY_MAGIC.to_vec();
+        for count in [2_u32, 2] {
+            expected.extend(count.to_le_bytes());
+        }
+        for size in [100.0_f32, 80.0] {
+            expected.extend(size.to_le_bytes());
+        }
+        for id in [0_u32, 1] {
+            expected.extend(id.to_le_bytes());
+        }
+        expected.extend([3_u8, 4]);
+        for (step, positions) in [
+            (5_u64, [1.5_f32, 2.5, 3.0, 4.0]),
+            (15, [2.0, 3.0, 3.5, 4.5]),
+        ] {
+            expected.extend(step.to_le_bytes());
+            for value in positions {
+                expected.extend(value.to_le_bytes());
+            }
+        }
+
+        assert_eq!(bytes.len(), 8 + 4 * 2 + 4 * 2 + 4 * 2 + 2 + 2 * (8 + 4 * 4));
+        assert_eq!(bytes, expected);
+    }
+
+    #[test]
+    fn csv_has_a_row_per_particle_per_frame() {
+        let recorder =
+            TrajectoryRecorder::new(PARAMS, 0, &particles(&[(1.5, 2.5), (3.0, 4.0)]), WORLD_SIZE);
+
+        assert_eq!(
+            recorder.to_csv(),
+            "step,id,kind,x,y\n0,0,3,1.5,2.5\n0,1,4,3,4\n"
+        );
+    }
+}
@@ -0,0 +1,429 @@
+use array_init::array_init;
+use d3dx12::{HeapProperties, Mappable, ResourceDesc, ShaderBytecode};
+use rand::{rngs::StdRng, SeedableRng};
+use std::mem::{size_of, size_of_val};
+use vek::Vec2;
+use windows::{
+    core::HSTRING,
+    Win32::Graphics::Direct3D12::{
+        ID3D12Device, ID3D12GraphicsCommandList, ID3D12PipelineState, ID3D12Resource,
+        ID3D12RootSignature, D3D12_COMPUTE_PIPELINE_STATE_DESC, D3D12_HEAP_FLAG_NONE,
+        D3D12_HEAP_TYPE, D3D12_HEAP_TYPE_DEFAULT, D3D12_HEAP_TYPE_READBACK, D3D12_HEAP_TYPE_UPLOAD,
+        D3D12_RESOURCE_STATES, D3D12_RESOURCE_STATE_COMMON, D3D12_RESOURCE_STATE_COPY_DEST,
+    },
+};
+
+use super::{metrics::Metrics, Particle, ParticleKind, Rule, Rules, ShaderGlobalConstants};
+use crate::raster::Vertex;
+
+pub struct World {
+    shader_constants: ShaderGlobalConstants,
+    staging_buffers: [ID3D12Resource; 2],
+    reset_particles: bool,
+    /// Number of kinds that new particles are drawn from.
+    species: u32,
+    /// Seed that `rng` started from.
+    seed: u64,
+    /// Places the particles each time they are reset, so that a world created
+    /// with the same seed starts out the same way.
+    rng: StdRng,
+    /// Particles that resetting puts back instead of placing new ones at
+    /// random, if some were imported.
+    imported: Option<Vec<Particle>>,
+
+    /// Copies of the particles buffer, used the same way as the staging
+    /// buffers: one is filled each frame and read back two frames later, once
+    /// the GPU has finished with it.
+    readback_buffers: [ID3D12Resource; 2],
+    /// The step that each readback buffer holds the particles for, if it has
+    /// been filled and not yet read.
+    readback_pending: [Option<u64>; 2],
+    particles: Vec<Particle>,
+    particles_step: u64,
+    /// Number of steps since the particles were last reset.
+    step: u64,
+    metrics: Option<Metrics>,
+
+    /// Per-particle colours that replace the usual colour by kind.
+    color_override: Option<Vec<u32>>,
+    color_override_changed: bool,
+    color_override_buffer: ID3D12Resource,
+
+    vertex_buffer: ID3D12Resource,
+    particles_buffers: [ID3D12Resource; 2],
+    constant_buffer: ID3D12Resource,
+
+    rs: ID3D12RootSignature,
+    pso: ID3D12PipelineState,
+}
+
+impl World {
+    #[must_use]
+    pub fn new(
+        device: &ID3D12Device,
+        num_particles: usize,
+        size: Vec2<f32>,
+        species: u32,
+        seed: u64,
+    ) -> Self {
+        let shader_constants = ShaderGlobalConstants::new(num_particles, size);
+
+        let particle_buffer_size = num_particles * size_of::<Particle>();
+        let vertex_buffer_size = num_particles * size_of::<Vertex>();
+        let color_buffer_size = num_particles * size_of::<u32>();
+
+        let num_rules = (ParticleKind::MAX * ParticleKind::MAX) as usize;
+        let constant_buffer_size =
+            size_of::<ShaderGlobalConstants>() + size_of::<Rule>() * num_rules;
+
+        let rs = create_root_signature(device);
+        let pso = create_pipeline_state(device, &rs);
+
+        World {
+            shader_constants,
+            vertex_buffer: create_buffer(device, vertex_buffer_size, "vertex_buffer"),
+            particles_buffers: array_init(|i| {
+                create_buffer(
+                    device,
+                    particle_buffer_size,
+                    format!("particles-{i}").as_str(),
+                )
+            }),
+            staging_buffers: array_init(|i| {
+                create_upload_buffer(
+                    device,
+                    particle_buffer_size + constant_buffer_size + color_buffer_size,
+                    format!("staging-{i}").as_str(),
+                )
+            }),
+            constant_buffer: create_buffer(device, constant_buffer_size, "constant_buffer"),
+
+            reset_particles: true,
+            species,
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+            imported: None,
+
+            readback_buffers: array_init(|i| {
+                create_readback_buffer(
+                    device,
+                    particle_buffer_size,
+                    format!("readback-{i}").as_str(),
+                )
+            }),
+            readback_pending: [None; 2],
+            particles: Vec::new(),
+            particles_step: 0,
+            step: 0,
+            metrics: None,
+
+            color_override: None,
+            color_override_changed: false,
+            color_override_buffer: create_buffer(device, color_buffer_size, "color_override"),
+
+            rs,
+            pso,
+        }
+    }
+
+    pub fn settings(&mut self) -> &mut ShaderGlobalConstants {
+        &mut self.shader_constants
+    }
+
+    #[must_use]
+    pub fn num_particles(&self) -> usize {
+        self.shader_constants.num_particles as usize
+    }
+
+    #[must_use]
+    pub fn species(&self) -> u32 {
+        self.species
+    }
+
+    /// The seed the particles were first placed with. Resetting them again
+    /// carries on drawing from the same generator, so only the first placement
+    /// can be reproduced from it.
+    #[must_use]
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Starts again with a different world size, number of kinds and seed,
+    /// keeping the same number of particles.
+    pub fn restart(&mut self, size: Vec2<f32>, species: u32, seed: u64) {
+        self.shader_constants.world_size = size.into_array();
+        self.species = species;
+        self.seed = seed;
+        self.rng = StdRng::seed_from_u64(seed);
+        self.imported = None;
+        self.reset_particles();
+    }
+
+    /// Starts again from `particles`, which there must be as many of as there
+    /// are in the world. Resetting the particles goes back to these until the
+    /// world is restarted.
+    pub fn import(&mut self, particles: Vec<Particle>) {
+        assert_eq!(particles.len(), self.num_particles());
+        self.imported = Some(particles);
+        self.reset_particles();
+    }
+
+    pub fn reset_particles(&mut self) {
+        self.reset_particles = true;
+        self.readback_pending = [None; 2];
+        self.particles.clear();
+        self.metrics = None;
+    }
+
+    /// The particles as of a couple of frames ago. Empty until the first copy
+    /// has been read back, and again briefly after the particles are reset.
+    #[must_use]
+    pub fn particles(&self) -> &[Particle] {
+        &self.particles
+    }
+
+    /// The step that `particles` were taken at.
+    #[must_use]
+    pub fn particles_step(&self) -> u64 {
+        self.particles_step
+    }
+
+    /// Metrics for the particles returned by `particles`.
+    #[must_use]
+    pub fn metrics(&self) -> Option<&Metrics> {
+        self.metrics.as_ref()
+    }
+
+    /// Draws each particle with the given colour, packed as ABGR, instead of
+    /// the colour for its kind. `None` goes back to colouring by kind.
+    #[must_use]
+    pub fn color_override(&self) -> Option<&[u32]> {
+        self.color_override.as_deref()
+    }
+
+    pub fn set_color_override(&mut self, colors: Option<Vec<u32>>) {
+        if colors.is_none() && self.color_override.is_none() {
+            return;
+        }
+
+        if let Some(colors) = &colors {
+            assert_eq!(colors.len(), self.shader_constants.num_particles as usize);
+        }
+        self.shader_constants.use_color_override = u32::from(colors.is_some());
+        self.color_override = colors;
+        self.color_override_changed = true;
+    }
+
+    pub fn update(&mut self, rules: &Rules, cl: &ID3D12GraphicsCommandList) {
+        if self.reset_particles {
+            self.step = 0;
+        }
+
+        self.read_back_particles(cl);
+        self.update_buffers(rules, cl);
+        self.reset_particles = false;
+
+        unsafe {
+            cl.SetComputeRootSignature(&self.rs);
+            cl.SetPipelineState(&self.pso);
+            cl.SetComputeRootConstantBufferView(0, self.constant_buffer.GetGPUVirtualAddress());
+            cl.SetComputeRootShaderResourceView(
+                1,
+                self.constant_buffer.GetGPUVirtualAddress()
+                    + size_of::<ShaderGlobalConstants>() as u64,
+            );
+            cl.SetComputeRootShaderResourceView(
+                2,
+                self.particles_buffers[0].GetGPUVirtualAddress(),
+            );
+            cl.SetComputeRootUnorderedAccessView(
+                3,
+                self.particles_buffers[1].GetGPUVirtualAddress(),
+            );
+            cl.SetComputeRootUnorderedAccessView(4, self.vertex_buffer.GetGPUVirtualAddress());
+            cl.SetComputeRootShaderResourceView(
+                5,
+                self.color_override_buffer.GetGPUVirtualAddress(),
+            );
+            cl.Dispatch(self.shader_constants.num_particles.div_ceil(32), 1, 1);
+        }
+
+        self.staging_buffers.swap(0, 1);
+        self.readback_buffers.swap(0, 1);
+        self.readback_pending.swap(0, 1);
+        self.particles_buffers.swap(0, 1);
+        self.step += 1;
+    }
+
+    fn read_back_particles(&mut self, cl: &ID3D12GraphicsCommandList) {
+        if let Some(step) = self.readback_pending[0].take() {
+            let mapped = self.readback_buffers[0].map::<Particle>();
+            self.particles.clear();
+            self.particles.extend_from_slice(mapped.as_slice());
+            self.particles_step = step;
+
+            let world_size = Vec2::from(self.shader_constants.world_size);
+            self.metrics = Some(Metrics::measure(&self.particles, world_size));
+        }
+
+        // Resetting writes to the particles buffer later in this command list,
+        // which isn't allowed after copying from it without a barrier.
+        if !self.reset_particles {
+            unsafe {
+                cl.CopyResource(&self.readback_buffers[0], &self.particles_buffers[0]);
+            }
+            self.readback_pending[0] = Some(self.step);
+        }
+    }
+
+    fn update_buffers(&mut self, rules: &Rules, cl: &ID3D12GraphicsCommandList) {
+        unsafe {
+            let staging_dest = self.staging_buffers[0].clone();
+            let staging = &mut self.staging_buffers[0];
+            let mut dest = staging.map_raw();
+            let mut dest_offset = 0;
+
+            // Always copy the shader constants and rules
+            *dest.as_mut_offset(dest_offset) = self.shader_constants;
+            cl.CopyBufferRegion(
+                &self.constant_buffer,
+                u64::try_from(dest_offset).unwrap(),
+                &staging_dest,
+                u64::try_from(dest_offset).unwrap(),
+                u64::try_from(size_of_val(&self.shader_constants)).unwrap(),
+            );
+            dest_offset += isize::try_from(size_of_val(&self.shader_constants)).unwrap();
+
+            *dest.as_mut_offset(dest_offset) = *rules;
+            cl.CopyBufferRegion(
+                &self.constant_buffer,
+                u64::try_from(dest_offset).unwrap(),
+                &staging_dest,
+                u64::try_from(dest_offset).unwrap(),
+                size_of_val(rules) as u64,
+            );
+            dest_offset += isize::try_from(size_of_val(rules)).unwrap();
+
+            // Copy a new set of particles if needed
+            if self.reset_particles {
+                let size = Vec2::from(self.shader_constants.world_size);
+                let num_particles = self.shader_constants.num_particles;
+
+                let species = self.species;
+                let particles: Vec<_> = match &self.imported {
+                    Some(particles) => particles.clone(),
+                    None => (0..num_particles)
+                        .map(|id| Particle::new(id, size, species, &mut self.rng))
+                        .collect(),
+                };
+
+                let dest_particles = dest.as_mut_slice_offset(dest_offset, num_particles as usize);
+                dest_particles.copy_from_slice(particles.as_slice());
+
+                cl.CopyBufferRegion(
+                    &self.particles_buffers[0],
+                    0,
+                    &staging_dest,
+                    u64::try_from(dest_offset).unwrap(),
+                    u64::from(num_particles) * size_of::<Particle>() as u64,
+                );
+            }
+            let num_particles = self.shader_constants.num_particles as usize;
+            dest_offset += isize::try_from(num_particles * size_of::<Particle>()).unwrap();
+
+            // Copy the colour override if it has changed
+            if self.color_override_changed {
+                if let Some(colors) = &self.color_override {
+                    let dest_colors = dest.as_mut_slice_offset(dest_offset, num_particles);
+                    dest_colors.copy_from_slice(colors);
+
+                    cl.CopyBufferRegion(
+                        &self.color_override_buffer,
+                        0,
+                        &staging_dest,
+                        u64::try_from(dest_offset).unwrap(),
+                        size_of_val(colors.as_slice()) as u64,
+                    );
+                }
+                self.color_override_changed = false;
+            }
+        }
+    }
+
+    #[must_use]
+    pub fn get_vertex_buffer(&self) -> (&ID3D12Resource, u32) {
+        (&self.vertex_buffer, self.shader_constants.num_particles)
+    }
+}
+
+fn create_buffer_with_type(
+    device: &ID3D12Device,
+    size: usize,
+    heap_type: D3D12_HEAP_TYPE,
+    initial_state: D3D12_RESOURCE_STATES,
+    name: &str,
+) -> ID3D12Resource {
+    unsafe {
+        let mut resource: Option<ID3D12Resource> = None;
+        device
+            .CreateCommittedResource(
+                &HeapProperties::standard(heap_type),
+                D3D12_HEAP_FLAG_NONE,
+                &ResourceDesc::buffer(size),
+                initial_state,
+                None,
+                &mut resource,
+            )
+            .unwrap();
+        let resource = resource.unwrap();
+        resource.SetName(&HSTRING::from(name)).unwrap();
+        resource
+    }
+}
+
+fn create_upload_buffer(device: &ID3D12Device, size: usize, name: &str) -> ID3D12Resource {
+    create_buffer_with_type(
+        device,
+        size,
+        D3D12_HEAP_TYPE_UPLOAD,
+        D3D12_RESOURCE_STATE_COMMON,
+        name,
+    )
+}
+
+fn create_readback_buffer(device: &ID3D12Device, size: usize, name: &str) -> ID3D12Resource {
+    create_buffer_with_type(
+        device,
+        size,
+        D3D12_HEAP_TYPE_READBACK,
+        D3D12_RESOURCE_STATE_COPY_DEST,
+        name,
+    )
+}
+
+fn create_buffer(device: &ID3D12Device, size: usize, name: &str) -> ID3D12Resource {
+    create_buffer_with_type(
+        device,
+        size,
+        D3D12_HEAP_TYPE_DEFAULT,
+        D3D12_RESOURCE_STATE_COMMON,
+        name,
+    )
+}
+
+fn create_root_signature(device: &ID3D12Device) -> ID3D12RootSignature {
+    let rs = include_bytes!(concat!(env!("OUT_DIR"), "/particle_life.root_signature"));
+    unsafe { device.CreateRootSignature(0, rs).unwrap() }
+}
+
+fn create_pipeline_state(device: &ID3D12Device, rs: &ID3D12RootSignature) -> ID3D12PipelineState {
+    let dxil = include_bytes!(concat!(env!("OUT_DIR"), "/particle_life.dxil"));
+
+    let desc = D3D12_COMPUTE_PIPELINE_STATE_DESC {
+        pRootSignature: unsafe { std::mem::transmute_copy(rs) },
+        CS: ShaderBytecode::from(dxil.as_slice()).into(),
+        ..Default::default()
+    };
+
+    unsafe { device.CreateComputePipelineState(&desc).unwrap() }
+}
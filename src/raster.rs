@@ -0,0 +1,144 @@
+use vek::{Mat4, Vec2, Vec4};
+
+/// A point to draw, as stored in the vertex buffer.
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct Vertex {
+    pub position: [f32; 2],
+    /// ABGR, so that the bytes in memory are red, green, blue, alpha.
+    pub color: u32,
+}
+
+/// An 8-bit RGBA image, stored row by row from the top.
+#[derive(Clone)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+/// Cleared to the same colour as the viewer's render target.
+const BACKGROUND: [u8; 4] = [0, 0, 0, 255];
+
+impl Image {
+    #[must_use]
+    pub fn new(width: u32, height: u32) -> Self {
+        Image {
+            width,
+            height,
+            pixels: BACKGROUND.repeat(width as usize * height as usize),
+        }
+    }
+
+    #[cfg(test)]
+    #[must_use]
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let i = self.index(x, y);
+        self.pixels[i..i + 4].try_into().unwrap()
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        (y as usize * self.width as usize + x as usize) * 4
+    }
+}
+
+/// Draws the points that `PointsRenderer` draws, but on the CPU, so that images
+/// can be made without a GPU.
+///
+/// Each vertex is drawn as a single pixel, transforming it by `matrix` and then
+/// mapping it to the image the way Direct3D maps clip space to a viewport
+/// covering the render target. Later vertices are drawn over earlier ones and
+/// points that land outside the image are dropped.
+#[must_use]
+pub fn rasterise(vertices: &[Vertex], matrix: Mat4<f32>, width: u32, height: u32) -> Image {
+    let mut image = Image::new(width, height);
+    #[allow(clippy::cast_precision_loss)]
+    let size = Vec2::new(width as f32, height as f32);
+
+    for vertex in vertices {
+        let clip = matrix * Vec4::new(vertex.position[0], vertex.position[1], 0.0, 1.0);
+        if clip.w <= 0.0 {
+            continue;
+        }
+        let ndc = Vec2::new(clip.x, clip.y) / clip.w;
+
+        // Clip space has y up, but rows are stored from the top.
+        let pixel = Vec2::new(ndc.x + 1.0, 1.0 - ndc.y) * 0.5 * size;
+        if !(pixel.x >= 0.0 && pixel.y >= 0.0 && pixel.x < size.x && pixel.y < size.y) {
+            continue;
+        }
+
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let i = image.index(pixel.x as u32, pixel.y as u32);
+        image.pixels[i..i + 4].copy_from_slice(&vertex.color.to_le_bytes());
+    }
+
+    image
+}
+
+/// A matrix that fits the whole of a world of the given size into the view,
+/// stretching it if the image has a different aspect ratio.
+#[must_use]
+pub fn world_matrix(world_size: Vec2<f32>) -> Mat4<f32> {
+    let scale: Mat4<f32> = Mat4::scaling_3d(Vec2::new(2.0, 2.0) / world_size);
+    let translate: Mat4<f32> = Mat4::translation_2d(-world_size / 2.0);
+
+    scale * translate
+}
+
+#[cfg(test)]
+mod tests {
+    use vek::Vec2;
+
+    use super::{rasterise, world_matrix, Vertex, BACKGROUND};
+
+    const RED: u32 = 0xff00_00ff;
+    const GREEN: u32 = 0xff00_ff00;
+
+    fn vertex(x: f32, y: f32, color: u32) -> Vertex {
+        Vertex {
+            position: [x, y],
+            color,
+        }
+    }
+
+    fn draw(vertices: &[Vertex]) -> super::Image {
+        rasterise(vertices, world_matrix(Vec2::new(100.0, 100.0)), 10, 10)
+    }
+
+    #[test]
+    fn point_lands_on_its_pixel() {
+        let image = draw(&[vertex(15.0, 45.0, RED)]);
+
+        assert_eq!(image.pixel(1, 5), [255, 0, 0, 255]);
+        assert_eq!(image.pixel(0, 0), BACKGROUND);
+    }
+
+    #[test]
+    fn y_is_flipped() {
+        // World y points up, but the first row is the top of the image.
+        let image = draw(&[vertex(5.0, 95.0, RED), vertex(5.0, 5.0, GREEN)]);
+
+        assert_eq!(image.pixel(0, 0), [255, 0, 0, 255]);
+        assert_eq!(image.pixel(0, 9), [0, 255, 0, 255]);
+    }
+
+    #[test]
+    fn later_points_are_drawn_over_earlier_ones() {
+        let image = draw(&[vertex(55.0, 55.0, RED), vertex(55.0, 55.0, GREEN)]);
+
+        assert_eq!(image.pixel(5, 4), [0, 255, 0, 255]);
+    }
+
+    #[test]
+    fn points_outside_the_image_are_dropped() {
+        let image = draw(&[
+            vertex(-1.0, 50.0, RED),
+            vertex(101.0, 50.0, RED),
+            vertex(50.0, -1.0, RED),
+            vertex(50.0, 101.0, RED),
+        ]);
+
+        assert!(image.pixels.chunks(4).all(|pixel| pixel == BACKGROUND));
+    }
+}
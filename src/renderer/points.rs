@@ -20,7 +20,7 @@ use windows::{
 
 use d3dx12::{BlendDesc, HeapProperties, Mappable, RasterizerDesc, ResourceDesc, ShaderBytecode};
 
-use crate::camera::Camera;
+use crate::{camera::Camera, raster::Vertex};
 
 pub struct PointsRenderer {
     rs: ID3D12RootSignature,
@@ -88,13 +88,6 @@ impl PointsBuffers {
     }
 }
 
-#[repr(C)]
-#[derive(Copy, Clone)]
-pub struct Vertex {
-    pub position: [f32; 2],
-    pub color: u32,
-}
-
 //
 // PointsRenderer construction
 //
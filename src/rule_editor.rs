@@ -0,0 +1,187 @@
+use std::fmt::Write;
+
+use crate::particle_life::{ParticleKind, Rule, RuleGenerationParameters, Rules};
+
+/// Shows the rules as a grid of coloured cells, one per pair of particle kinds,
+/// and allows the rule for the selected pair to be edited.
+pub struct RuleEditor {
+    selected: (ParticleKind, ParticleKind),
+}
+
+const CELL_SIZE: [f32; 2] = [16.0, 16.0];
+const CURVE_SAMPLES: usize = 200;
+
+impl Default for RuleEditor {
+    fn default() -> Self {
+        let first = ParticleKind::all().next().unwrap();
+
+        RuleEditor {
+            selected: (first, first),
+        }
+    }
+}
+
+impl RuleEditor {
+    /// The pair of kinds whose rule is being edited.
+    pub fn selected(&self) -> (ParticleKind, ParticleKind) {
+        self.selected
+    }
+
+    /// Returns whether the rules were changed.
+    pub fn draw_ui(
+        &mut self,
+        imgui: &imgui::Ui,
+        rules: &mut Rules,
+        params: &RuleGenerationParameters,
+    ) -> bool {
+        self.draw_grid(imgui, rules);
+
+        let (a, b) = self.selected;
+        imgui.text(format!("{} -> {}", a.index(), b.index()));
+
+        let rule = rules.get_rule_mut(a, b);
+        let mut changed = imgui::Drag::new("force")
+            .range(-2.0, 2.0)
+            .speed(0.001)
+            .build(imgui, &mut rule.force);
+        changed |= imgui::Drag::new("min_distance")
+            .range(0.001, rule.max_distance)
+            .speed(0.1)
+            .build(imgui, &mut rule.min_distance);
+        changed |= imgui::Drag::new("max_distance")
+            .range(rule.min_distance, 500.0)
+            .speed(0.1)
+            .build(imgui, &mut rule.max_distance);
+
+        draw_force_curve(imgui, rule);
+
+        if imgui.button("Randomize Cell") {
+            rules.randomize_rule(a, b, params);
+            changed = true;
+        }
+        imgui.same_line();
+        if imgui.button("Randomize Row") {
+            rules.randomize_row(a, params);
+            changed = true;
+        }
+        imgui.same_line();
+        if imgui.button("Randomize Column") {
+            rules.randomize_column(b, params);
+            changed = true;
+        }
+
+        changed
+    }
+
+    fn draw_grid(&mut self, imgui: &imgui::Ui, rules: &Rules) {
+        // Column headers show the kind that is being reacted to
+        imgui.dummy(CELL_SIZE);
+        for b in ParticleKind::all() {
+            imgui.same_line();
+            kind_button(imgui, "column", b);
+        }
+
+        for a in ParticleKind::all() {
+            kind_button(imgui, "row", a);
+
+            for b in ParticleKind::all() {
+                imgui.same_line();
+
+                let rule = rules.get_rule(a, b);
+                let clicked = imgui
+                    .color_button_config(
+                        format!("##rule{}-{}", a.index(), b.index()),
+                        force_color(rule.force),
+                    )
+                    .size(CELL_SIZE)
+                    .tooltip(false)
+                    .build();
+
+                if imgui.is_item_hovered() {
+                    imgui.tooltip_text(format!(
+                        "{} -> {}\nforce: {:.3}\nmin_distance: {:.1}\nmax_distance: {:.1}",
+                        a.index(),
+                        b.index(),
+                        rule.force,
+                        rule.min_distance,
+                        rule.max_distance
+                    ));
+                }
+
+                if self.selected == (a, b) {
+                    imgui
+                        .get_window_draw_list()
+                        .add_rect(
+                            imgui.item_rect_min(),
+                            imgui.item_rect_max(),
+                            [1.0, 1.0, 1.0, 1.0],
+                        )
+                        .thickness(2.0)
+                        .build();
+                }
+
+                if clicked {
+                    self.selected = (a, b);
+                }
+            }
+        }
+    }
+}
+
+/// Plots force against distance for the rule, from 0 to a little past
+/// `max_distance` so that the point where the force drops to zero is visible.
+#[allow(clippy::cast_precision_loss)]
+fn draw_force_curve(imgui: &imgui::Ui, rule: &Rule) {
+    let end = rule.max_distance * 1.1;
+    let curve = rule.force_curve(end, CURVE_SAMPLES);
+    let forces: Vec<f32> = curve.iter().map(|(_, force)| *force).collect();
+
+    let limit = forces.iter().fold(0.001_f32, |limit, f| limit.max(f.abs()));
+
+    imgui
+        .plot_lines("##force_curve", &forces)
+        .scale_min(-limit)
+        .scale_max(limit)
+        .graph_size([CURVE_SAMPLES as f32, 80.0])
+        .overlay_text(format!("force vs distance (0 - {end:.0})"))
+        .build();
+
+    if imgui.is_item_hovered() {
+        let [x, _] = imgui.io().mouse_pos;
+        let [min_x, _] = imgui.item_rect_min();
+        let [max_x, _] = imgui.item_rect_max();
+        let t = ((x - min_x) / (max_x - min_x)).clamp(0.0, 1.0);
+        let distance = t * end;
+        imgui.tooltip_text(format!(
+            "distance: {distance:.1}\nforce: {:.3}",
+            rule.force_at(distance)
+        ));
+    }
+
+    imgui.same_line();
+    if imgui.button("Copy CSV") {
+        let mut csv = String::from("distance,force\n");
+        for (distance, force) in &curve {
+            writeln!(csv, "{distance},{force}").unwrap();
+        }
+        imgui.set_clipboard_text(csv);
+    }
+}
+
+fn kind_button(imgui: &imgui::Ui, id: &str, kind: ParticleKind) {
+    imgui
+        .color_button_config(format!("##{id}{}", kind.index()), kind.as_rgba())
+        .size(CELL_SIZE)
+        .tooltip(false)
+        .build();
+}
+
+/// Green for attraction, red for repulsion, brighter for stronger forces.
+fn force_color(force: f32) -> [f32; 4] {
+    let amount = force.abs().min(1.0);
+    if force >= 0.0 {
+        [0.0, amount, 0.0, 1.0]
+    } else {
+        [amount, 0.0, 0.0, 1.0]
+    }
+}
@@ -0,0 +1,79 @@
+#[cfg(windows)]
+use std::path::PathBuf;
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::Path,
+};
+
+use anyhow::{Context, Result};
+use vek::Vec2;
+
+use crate::{
+    particle_life::{scene::Scene, Particle},
+    raster::{self, Image},
+};
+
+/// Keyword of the PNG text chunk that holds the scene.
+const SCENE_KEYWORD: &str = "dplife-scene";
+
+/// Draws the whole world into an image of the given size.
+pub fn render_world(particles: &[Particle], world_size: Vec2<f32>, size: [u32; 2]) -> Image {
+    let vertices: Vec<_> = particles.iter().map(Particle::vertex).collect();
+    raster::rasterise(
+        &vertices,
+        raster::world_matrix(world_size),
+        size[0],
+        size[1],
+    )
+}
+
+/// Writes `image` as a PNG, with `scene` stored in a text chunk so that it can
+/// be restored with `load_scene`.
+pub fn save(path: &Path, image: &Image, scene: Option<&Scene>) -> Result<()> {
+    let file = File::create(path).with_context(|| format!("creating {}", path.display()))?;
+
+    let mut encoder = png::Encoder::new(BufWriter::new(file), image.width, image.height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    if let Some(scene) = scene {
+        encoder.add_itxt_chunk(SCENE_KEYWORD.to_owned(), scene.to_toml()?)?;
+    }
+
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&image.pixels)?;
+    writer.finish()?;
+
+    Ok(())
+}
+
+/// Reads the scene stored in a PNG written by `save`.
+pub fn load_scene(path: &Path) -> Result<Scene> {
+    let file = File::open(path).with_context(|| format!("opening {}", path.display()))?;
+    let reader = png::Decoder::new(BufReader::new(file))
+        .read_info()
+        .with_context(|| format!("reading {}", path.display()))?;
+
+    let chunk = reader
+        .info()
+        .utf8_text
+        .iter()
+        .find(|chunk| chunk.keyword == SCENE_KEYWORD)
+        .with_context(|| format!("{} has no scene in it", path.display()))?;
+
+    Scene::parse(&chunk.get_text()?).with_context(|| format!("parsing scene in {}", path.display()))
+}
+
+/// The first numbered screenshot file in the working directory that doesn't
+/// exist yet.
+#[cfg(windows)]
+pub fn next_path() -> PathBuf {
+    let mut i = 0;
+    loop {
+        let path = PathBuf::from(format!("screenshot_{i:03}.png"));
+        if !path.exists() {
+            return path;
+        }
+        i += 1;
+    }
+}
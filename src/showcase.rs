@@ -0,0 +1,114 @@
+use std::time::{Duration, Instant};
+
+use crate::particle_life::classify::{Behaviour, Classification};
+
+/// Time given to new rules to form structures before they are judged.
+const SETTLE_TIME: Duration = Duration::from_secs(5);
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Rule sets are only skipped when classified with at least this confidence.
+const SKIP_CONFIDENCE: f32 = 0.5;
+
+#[allow(clippy::struct_excessive_bools)]
+pub struct ShowcaseSettings {
+    /// Seconds to show each rule set for.
+    pub interval: f32,
+    /// Pick from the presets rather than generating random rules.
+    pub use_presets: bool,
+    /// Start each rule set from fresh particles instead of morphing from the
+    /// previous one.
+    pub reset_particles: bool,
+    pub hide_ui: bool,
+    /// Move on early from rule sets that settle into something static or
+    /// spread out evenly.
+    pub skip_collapsed: bool,
+}
+
+impl Default for ShowcaseSettings {
+    fn default() -> Self {
+        ShowcaseSettings {
+            interval: 30.0,
+            use_presets: false,
+            reset_particles: false,
+            hide_ui: true,
+            skip_collapsed: true,
+        }
+    }
+}
+
+pub enum ShowcaseChange {
+    /// The current rule set has been shown for long enough.
+    Next,
+    /// The current rule set has collapsed into the given behaviour.
+    Skip(Behaviour),
+}
+
+/// Cycles through rule sets on a timer, for unattended displays. The timer
+/// only runs while the simulation does, so pausing holds the current rules.
+pub struct Showcase {
+    last_update: Instant,
+    /// Time the current rules have been shown for.
+    elapsed: Duration,
+    since_check: Duration,
+}
+
+impl Showcase {
+    pub fn new() -> Self {
+        Showcase {
+            last_update: Instant::now(),
+            elapsed: Duration::ZERO,
+            since_check: Duration::ZERO,
+        }
+    }
+
+    /// Checks whether it's time to move on to new rules. `behaviour` is the
+    /// current classification of the world, and `settle_time` is extra time to
+    /// allow before judging it, such as the length of a morph. Time spent
+    /// `paused` doesn't count.
+    pub fn update(
+        &mut self,
+        settings: &ShowcaseSettings,
+        behaviour: Option<Classification>,
+        settle_time: Duration,
+        paused: bool,
+    ) -> Option<ShowcaseChange> {
+        let now = Instant::now();
+        let delta = now - self.last_update;
+        self.last_update = now;
+        if paused {
+            return None;
+        }
+        self.elapsed += delta;
+        self.since_check += delta;
+
+        if self.elapsed.as_secs_f32() >= settings.interval {
+            return Some(ShowcaseChange::Next);
+        }
+
+        if settings.skip_collapsed
+            && self.elapsed >= settle_time + SETTLE_TIME
+            && self.since_check >= CHECK_INTERVAL
+        {
+            self.since_check = Duration::ZERO;
+            if let Some(behaviour) = behaviour.filter(|b| is_collapsed(*b)) {
+                return Some(ShowcaseChange::Skip(behaviour.behaviour));
+            }
+        }
+
+        None
+    }
+
+    /// Restarts the timer after the rules have been changed.
+    pub fn changed(&mut self) {
+        self.elapsed = Duration::ZERO;
+        self.since_check = Duration::ZERO;
+    }
+}
+
+fn is_collapsed(classification: Classification) -> bool {
+    let collapsed = matches!(
+        classification.behaviour,
+        Behaviour::Frozen | Behaviour::Crystals | Behaviour::Gas
+    );
+    collapsed && classification.confidence >= SKIP_CONFIDENCE
+}
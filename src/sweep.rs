@@ -0,0 +1,506 @@
+use std::{fmt::Write as _, fs, ops::Range, path::PathBuf};
+
+use anyhow::{ensure, Context, Result};
+use clap::{Args, ValueEnum};
+use rand::{rngs::StdRng, seq::IndexedRandom, Rng, SeedableRng};
+
+use crate::particle_life::{
+    classify::classify,
+    cpu::{self, CpuWorld},
+    fitness,
+    metrics::Metrics,
+    RuleGenerationParameters, Rules, ShaderGlobalConstants, Symmetry,
+};
+
+/// Run headless simulations over a grid or random sample of settings and rule
+/// generation parameters, and write the resulting metrics to a CSV file.
+///
+/// Each parameter takes either a range, such as 0.8..0.95, or a list of
+/// values, such as 0.8,0.9,0.95. Parameters that aren't given keep their
+/// defaults. The distributions that rule distances and forces are drawn from
+/// are swept by scaling them.
+#[derive(Args)]
+pub struct SweepArgs {
+    #[arg(long, value_enum, default_value_t = Mode::Grid)]
+    mode: Mode,
+
+    /// Number of evenly spaced values taken from each range in a grid sweep.
+    #[arg(long, default_value_t = 5)]
+    grid_steps: usize,
+
+    /// Number of configurations in a random sweep.
+    #[arg(long, default_value_t = 50)]
+    samples: usize,
+
+    /// Seed for picking the configurations in a random sweep.
+    #[arg(long, default_value_t = 0)]
+    sample_seed: u64,
+
+    /// Seeds for the rules and initial particle positions. Every
+    /// configuration is run once with each.
+    #[arg(long, value_delimiter = ',', default_value = "1")]
+    seeds: Vec<u64>,
+
+    #[arg(long, default_value_t = 2000)]
+    particles: usize,
+
+    #[arg(long, default_value_t = 500)]
+    steps: usize,
+
+    /// Number of final steps that the metrics are averaged over.
+    #[arg(long, default_value_t = 100)]
+    measure_steps: usize,
+
+    #[arg(long, value_parser = parse_sweep)]
+    friction: Option<Sweep>,
+
+    #[arg(long, value_parser = parse_sweep)]
+    force_multiplier: Option<Sweep>,
+
+    #[arg(long, value_parser = parse_sweep)]
+    attraction_probability: Option<Sweep>,
+
+    #[arg(long, value_parser = parse_sweep)]
+    sparsity: Option<Sweep>,
+
+    #[arg(long, value_parser = parse_sweep)]
+    self_attraction: Option<Sweep>,
+
+    /// Scale of the distribution that minimum distances are drawn from.
+    #[arg(long, value_parser = parse_sweep)]
+    min_distance_scale: Option<Sweep>,
+
+    /// Scale of the distribution that maximum distances are drawn from.
+    #[arg(long, value_parser = parse_sweep)]
+    max_distance_scale: Option<Sweep>,
+
+    /// Scale of the distribution that forces are drawn from.
+    #[arg(long, value_parser = parse_sweep)]
+    force_scale: Option<Sweep>,
+
+    /// Symmetries to try, such as none,symmetric,antisymmetric.
+    #[arg(long, value_delimiter = ',', value_parser = parse_symmetry)]
+    symmetry: Vec<Symmetry>,
+
+    /// Whether to arrange the kinds in a predator-prey cycle, such as
+    /// false,true.
+    #[arg(long, value_delimiter = ',')]
+    predator_prey_cycle: Vec<bool>,
+
+    /// CSV file to write the results to.
+    #[arg(long, default_value = "sweep.csv")]
+    output: PathBuf,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Mode {
+    /// Every combination of the values.
+    Grid,
+    /// Configurations picked at random from the values.
+    Random,
+}
+
+/// The values to try for one parameter.
+#[derive(Clone)]
+enum Sweep {
+    Range(Range<f32>),
+    List(Vec<f32>),
+}
+
+impl Sweep {
+    #[allow(clippy::cast_precision_loss)]
+    fn grid(&self, steps: usize) -> Vec<f32> {
+        match self {
+            Sweep::Range(range) if steps > 1 => (0..steps)
+                .map(|i| range.start + (range.end - range.start) * i as f32 / (steps - 1) as f32)
+                .collect(),
+            Sweep::Range(range) => vec![range.start],
+            Sweep::List(values) => values.clone(),
+        }
+    }
+
+    fn sample(&self, rng: &mut impl Rng) -> f32 {
+        match self {
+            Sweep::Range(range) if range.start < range.end => rng.random_range(range.clone()),
+            Sweep::Range(range) => range.start,
+            Sweep::List(values) => *values.choose(rng).unwrap(),
+        }
+    }
+}
+
+fn parse_sweep(text: &str) -> Result<Sweep, String> {
+    let parse = |value: &str| {
+        value
+            .trim()
+            .parse::<f32>()
+            .ok()
+            .filter(|v| v.is_finite())
+            .ok_or_else(|| format!("'{value}' is not a number"))
+    };
+
+    if let Some((start, end)) = text.split_once("..") {
+        let range = parse(start)?..parse(end)?;
+        if range.start > range.end {
+            return Err("the start of a range can't be after its end".to_owned());
+        }
+        Ok(Sweep::Range(range))
+    } else {
+        Ok(Sweep::List(
+            text.split(',').map(parse).collect::<Result<_, _>>()?,
+        ))
+    }
+}
+
+fn parse_symmetry(name: &str) -> Result<Symmetry, String> {
+    Symmetry::ALL
+        .into_iter()
+        .find(|s| s.name().eq_ignore_ascii_case(name))
+        .ok_or_else(|| {
+            let names: Vec<_> = Symmetry::ALL.iter().map(|s| s.name()).collect();
+            format!("expected one of {}", names.join(", "))
+        })
+}
+
+/// One point in the sweep.
+#[derive(Clone)]
+struct Configuration {
+    friction: f32,
+    force_multiplier: f32,
+    attraction_probability: f32,
+    sparsity: f32,
+    self_attraction: f32,
+    min_distance_scale: f32,
+    max_distance_scale: f32,
+    force_scale: f32,
+    symmetry: Symmetry,
+    predator_prey_cycle: bool,
+}
+
+type Setter = fn(&mut Configuration, f32);
+
+impl Configuration {
+    fn generation_parameters(&self) -> RuleGenerationParameters {
+        let defaults = RuleGenerationParameters::default();
+        RuleGenerationParameters {
+            min_distance: defaults.min_distance.scaled(self.min_distance_scale),
+            max_distance: defaults.max_distance.scaled(self.max_distance_scale),
+            force: defaults.force.scaled(self.force_scale),
+            attraction_probability: self.attraction_probability,
+            symmetry: self.symmetry,
+            sparsity: self.sparsity,
+            self_attraction: self.self_attraction,
+            predator_prey_cycle: self.predator_prey_cycle,
+            species: defaults.species,
+        }
+    }
+}
+
+impl Default for Configuration {
+    fn default() -> Self {
+        let params = RuleGenerationParameters::default();
+        Configuration {
+            friction: ShaderGlobalConstants::DEFAULT_FRICTION,
+            force_multiplier: ShaderGlobalConstants::DEFAULT_FORCE_MULTIPLIER,
+            attraction_probability: params.attraction_probability,
+            sparsity: params.sparsity,
+            self_attraction: params.self_attraction,
+            min_distance_scale: 1.0,
+            max_distance_scale: 1.0,
+            force_scale: 1.0,
+            symmetry: params.symmetry,
+            predator_prey_cycle: params.predator_prey_cycle,
+        }
+    }
+}
+
+impl SweepArgs {
+    fn numeric_parameters(&self) -> [(Option<&Sweep>, Setter); 8] {
+        [
+            (self.friction.as_ref(), |c, v| c.friction = v),
+            (self.force_multiplier.as_ref(), |c, v| {
+                c.force_multiplier = v;
+            }),
+            (self.attraction_probability.as_ref(), |c, v| {
+                c.attraction_probability = v;
+            }),
+            (self.sparsity.as_ref(), |c, v| c.sparsity = v),
+            (self.self_attraction.as_ref(), |c, v| c.self_attraction = v),
+            (self.min_distance_scale.as_ref(), |c, v| {
+                c.min_distance_scale = v;
+            }),
+            (self.max_distance_scale.as_ref(), |c, v| {
+                c.max_distance_scale = v;
+            }),
+            (self.force_scale.as_ref(), |c, v| c.force_scale = v),
+        ]
+    }
+
+    fn grid(&self) -> Vec<Configuration> {
+        let mut configurations = vec![Configuration::default()];
+
+        for (sweep, set) in self.numeric_parameters() {
+            if let Some(sweep) = sweep {
+                configurations = expand(&configurations, &sweep.grid(self.grid_steps), set);
+            }
+        }
+        configurations = expand(&configurations, &self.symmetry, |c, symmetry| {
+            c.symmetry = symmetry;
+        });
+        configurations = expand(&configurations, &self.predator_prey_cycle, |c, cycle| {
+            c.predator_prey_cycle = cycle;
+        });
+
+        configurations
+    }
+
+    fn random(&self) -> Vec<Configuration> {
+        let mut rng = StdRng::seed_from_u64(self.sample_seed);
+
+        (0..self.samples)
+            .map(|_| {
+                let mut c = Configuration::default();
+                for (sweep, set) in self.numeric_parameters() {
+                    if let Some(sweep) = sweep {
+                        set(&mut c, sweep.sample(&mut rng));
+                    }
+                }
+                if let Some(symmetry) = self.symmetry.choose(&mut rng) {
+                    c.symmetry = *symmetry;
+                }
+                if let Some(cycle) = self.predator_prey_cycle.choose(&mut rng) {
+                    c.predator_prey_cycle = *cycle;
+                }
+                c
+            })
+            .collect()
+    }
+}
+
+/// Every combination of one of `configurations` with one of `values`. If there
+/// are no values the configurations are left as they are.
+fn expand<T: Copy>(
+    configurations: &[Configuration],
+    values: &[T],
+    set: impl Fn(&mut Configuration, T),
+) -> Vec<Configuration> {
+    if values.is_empty() {
+        return configurations.to_vec();
+    }
+
+    configurations
+        .iter()
+        .flat_map(|c| {
+            values.iter().map(|v| {
+                let mut c = c.clone();
+                set(&mut c, *v);
+                c
+            })
+        })
+        .collect()
+}
+
+struct Outcome {
+    finite: bool,
+    metrics: Metrics,
+    behaviour: &'static str,
+    confidence: f32,
+}
+
+fn evaluate(configuration: &Configuration, seed: u64, args: &SweepArgs) -> Outcome {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let rules = Rules::new_random_with_rng(&configuration.generation_parameters(), &mut rng);
+
+    let world_size = cpu::default_world_size(args.particles);
+    let mut world = CpuWorld::new(args.particles, world_size, &mut rng);
+    world.settings().friction = configuration.friction;
+    world.settings().force_multiplier = configuration.force_multiplier;
+
+    let mut history = Vec::with_capacity(args.measure_steps);
+    let mut finite = true;
+    for step in 0..args.steps {
+        world.step(&rules);
+        if args.steps - step <= args.measure_steps {
+            history.push(Metrics::measure(world.particles(), world_size));
+        }
+        if !fitness::all_finite(world.particles()) {
+            finite = false;
+            break;
+        }
+    }
+
+    let classification = classify(&history);
+    Outcome {
+        finite,
+        metrics: Metrics::mean(&history),
+        behaviour: classification.map_or("unknown", |c| c.behaviour.name()),
+        confidence: classification.map_or(0.0, |c| c.confidence),
+    }
+}
+
+pub fn run(args: &SweepArgs) -> Result<()> {
+    ensure!(args.particles > 0, "particles must be at least 1");
+    ensure!(!args.seeds.is_empty(), "at least one seed is needed");
+    ensure!(args.grid_steps > 0, "grid steps must be at least 1");
+    ensure!(
+        args.measure_steps <= args.steps,
+        "measure steps can't be more than steps"
+    );
+
+    let configurations = match args.mode {
+        Mode::Grid => args.grid(),
+        Mode::Random => args.random(),
+    };
+    let runs = configurations.len() * args.seeds.len();
+    println!(
+        "{} configurations, {} seeds, {runs} runs",
+        configurations.len(),
+        args.seeds.len()
+    );
+
+    let mut csv = String::from(
+        "configuration,seed,friction,force_multiplier,attraction_probability,sparsity,\
+         self_attraction,min_distance_scale,max_distance_scale,force_scale,symmetry,\
+         predator_prey_cycle,finite,behaviour,confidence,",
+    );
+    csv.push_str(&Metrics::csv_header());
+    csv.push('\n');
+
+    let mut run = 0;
+    for (index, c) in configurations.iter().enumerate() {
+        for seed in &args.seeds {
+            run += 1;
+            let outcome = evaluate(c, *seed, args);
+            println!(
+                "{run}/{runs}: configuration {index}, seed {seed}: {}",
+                outcome.behaviour
+            );
+
+            writeln!(
+                csv,
+                "{index},{seed},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+                c.friction,
+                c.force_multiplier,
+                c.attraction_probability,
+                c.sparsity,
+                c.self_attraction,
+                c.min_distance_scale,
+                c.max_distance_scale,
+                c.force_scale,
+                c.symmetry.name(),
+                c.predator_prey_cycle,
+                outcome.finite,
+                outcome.behaviour,
+                outcome.confidence,
+                outcome.metrics.csv_row()
+            )?;
+        }
+    }
+
+    fs::write(&args.output, csv).with_context(|| format!("writing {}", args.output.display()))?;
+    println!("Wrote {}", args.output.display());
+    Ok(())
+}
+
+#[cfg(test)]
+// Parsed and evenly spaced values are compared with the exact values they
+// should produce.
+#[allow(clippy::float_cmp)]
+mod tests {
+    use clap::Parser;
+
+    use super::{parse_sweep, Sweep, SweepArgs};
+    use crate::particle_life::Symmetry;
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        sweep: SweepArgs,
+    }
+
+    fn args(arguments: &[&str]) -> SweepArgs {
+        Cli::try_parse_from(std::iter::once("sweep").chain(arguments.iter().copied()))
+            .unwrap()
+            .sweep
+    }
+
+    #[test]
+    fn parses_ranges() {
+        let Ok(Sweep::Range(range)) = parse_sweep("0.8..0.95") else {
+            panic!("expected a range");
+        };
+        assert_eq!(range, 0.8..0.95);
+
+        let Ok(Sweep::Range(range)) = parse_sweep(" -1 .. 1 ") else {
+            panic!("expected a range");
+        };
+        assert_eq!(range, -1.0..1.0);
+    }
+
+    #[test]
+    fn parses_lists() {
+        let Ok(Sweep::List(values)) = parse_sweep("0.8, 0.9,0.95") else {
+            panic!("expected a list");
+        };
+        assert_eq!(values, [0.8, 0.9, 0.95]);
+
+        let Ok(Sweep::List(values)) = parse_sweep("2") else {
+            panic!("expected a list");
+        };
+        assert_eq!(values, [2.0]);
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        for text in [
+            "", "a", "1,,2", "1..", "..1", "1..x", "inf", "0..NaN", "2..1",
+        ] {
+            assert!(parse_sweep(text).is_err(), "{text} should be rejected");
+        }
+    }
+
+    #[test]
+    fn grid_has_every_combination() {
+        let args = args(&[
+            "--grid-steps=3",
+            "--friction=0.8..0.9",
+            "--sparsity=0.1,0.2",
+            "--symmetry=none,symmetric",
+        ]);
+
+        let configurations = args.grid();
+        assert_eq!(configurations.len(), 3 * 2 * 2);
+
+        let mut frictions: Vec<_> = configurations.iter().map(|c| c.friction).collect();
+        frictions.dedup();
+        assert_eq!(frictions, [0.8, 0.85, 0.9]);
+        assert!(configurations[..4]
+            .iter()
+            .zip([0.1, 0.1, 0.2, 0.2])
+            .all(|(c, sparsity)| c.sparsity == sparsity));
+        assert!(configurations[..2]
+            .iter()
+            .zip([Symmetry::None, Symmetry::Symmetric])
+            .all(|(c, symmetry)| c.symmetry == symmetry));
+    }
+
+    #[test]
+    fn grid_without_parameters_is_the_defaults() {
+        assert_eq!(args(&[]).grid().len(), 1);
+    }
+
+    #[test]
+    fn random_picks_samples_from_the_values() {
+        let args = args(&[
+            "--mode=random",
+            "--samples=20",
+            "--friction=0.8..0.9",
+            "--sparsity=0.1,0.2",
+        ]);
+
+        let configurations = args.random();
+        assert_eq!(configurations.len(), 20);
+        assert!(configurations
+            .iter()
+            .all(|c| (0.8..0.9).contains(&c.friction) && [0.1, 0.2].contains(&c.sparsity)));
+    }
+}
@@ -0,0 +1,1576 @@
+use std::{
+    fs,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{self, Receiver},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+use vek::Vec2;
+
+use d3dx12::transition_barrier;
+use imgui::{Condition::Always, Drag, DragRange, TreeNodeFlags};
+use rand::{rngs::StdRng, seq::IndexedRandom, SeedableRng};
+
+use windows::Win32::Graphics::Direct3D12::{
+    D3D12_RESOURCE_STATE_PRESENT, D3D12_RESOURCE_STATE_RENDER_TARGET,
+};
+use winit::{
+    dpi::LogicalSize,
+    event::{ElementState, Event, KeyEvent, MouseScrollDelta, WindowEvent},
+    event_loop::EventLoop,
+    keyboard::{Key, NamedKey},
+    window::WindowBuilder,
+};
+
+use crate::{
+    camera::Camera,
+    config::ViewerConfig,
+    ecl,
+    frames::{self, FrameFormat, FrameParameters, FrameRecorder},
+    imgui_manager::ImguiManager,
+    particle_life::{
+        classify::{classify, Classification, CLASSIFICATION_WINDOW},
+        clusters::{self, ClusterParameters, Clustering},
+        metrics::{Metrics, MetricsHistory},
+        presets::{self, Preset},
+        rdf::{RadialDistribution, RdfParameters},
+        scene::Scene,
+        snapshot::{Import, SnapshotFormat},
+        tracking::OrganismTracker,
+        trajectory::{TrajectoryParameters, TrajectoryRecorder},
+        Distribution, DistributionKind, MutationParameters, Particle, ParticleKind,
+        RuleGenerationParameters, RuleMorph, Rules, Symmetry, World,
+    },
+    raster::{self, Image},
+    renderer::{points::PointsRenderer, Renderer},
+    rule_editor::RuleEditor,
+    screenshot,
+    showcase::{Showcase, ShowcaseChange, ShowcaseSettings},
+};
+
+enum ThreadMessage {
+    Quit,
+    Event(Event<()>),
+}
+
+pub fn run(config: ViewerConfig) {
+    println!("Seed: {}", config.seed);
+
+    let event_loop = EventLoop::new().unwrap();
+
+    let [width, height] = config.window_size;
+    let builder = WindowBuilder::new().with_inner_size(LogicalSize { width, height });
+
+    let window = builder.build(&event_loop).unwrap();
+
+    let renderer = Renderer::new(&window);
+
+    let (tx, rx) = mpsc::channel();
+
+    let imgui_manager = Arc::new(ImguiManager::new(window));
+    let imgui_manager_for_main_thread = imgui_manager.clone();
+
+    let mut main_thread = Some(thread::spawn(move || {
+        main_thread(&rx, renderer, imgui_manager_for_main_thread, &config);
+    }));
+
+    event_loop
+        .run(move |event, elwt| {
+            let pass_event_to_app = imgui_manager.lock().unwrap().handle_event(&event);
+
+            if let Event::WindowEvent {
+                event: WindowEvent::CloseRequested,
+                ..
+            } = event
+            {
+                if let Some(thread) = main_thread.take() {
+                    tx.send(ThreadMessage::Quit).unwrap();
+                    thread.join().unwrap();
+                }
+                elwt.exit();
+            }
+
+            if main_thread.is_some() && pass_event_to_app {
+                // event.to_static() consumes event, so we have to make this the
+                // last thing we do with it. See
+                // https://github.com/rust-windowing/winit/issues/1968.
+                tx.send(ThreadMessage::Event(event.clone())).unwrap();
+            }
+        })
+        .unwrap();
+}
+
+struct RenderedUI {
+    imgui_manager: Arc<Mutex<ImguiManager>>,
+    imgui_renderer: imgui_windows_d3d12_renderer::Renderer,
+}
+
+const METRICS_PLOT_WIDTH: f32 = 300.0;
+
+/// File, relative to the working directory, that organism tracks are exported
+/// to.
+const TRACKS_FILE: &str = "tracks.csv";
+
+/// File, relative to the working directory, that pair correlations are
+/// exported to.
+const RDF_FILE: &str = "rdf.csv";
+
+/// Files, relative to the working directory, that particle trajectories are
+/// exported to.
+const TRAJECTORIES_CSV_FILE: &str = "trajectories.csv";
+const TRAJECTORIES_BINARY_FILE: &str = "trajectories.bin";
+
+/// Name, relative to the working directory and without the extension, of the
+/// file that the particles are exported to.
+const PARTICLES_FILE: &str = "particles";
+
+/// Directory and files, relative to the working directory, that recorded
+/// frames are written to.
+const FRAMES_DIR: &str = "frames";
+const FRAMES_Y4M_FILE: &str = "frames.y4m";
+const FRAMES_GIF_FILE: &str = "frames.gif";
+
+#[derive(Default)]
+#[allow(clippy::struct_excessive_bools)]
+struct UIState {
+    new_rules: bool,
+    mutate_rules: bool,
+    reset_particles: bool,
+    paused: bool,
+    /// Advance one step while paused.
+    step_once: bool,
+    screenshot: bool,
+    snapshot_format: SnapshotFormat,
+    export_snapshot: bool,
+    /// Screenshot dropped onto the window, to restore the scene from.
+    load_scene: Option<PathBuf>,
+    /// CSV or PLY file dropped onto the window, to take the particles from.
+    import_particles: Option<PathBuf>,
+
+    rule_generation_parameters: RuleGenerationParameters,
+    mutation_parameters: MutationParameters,
+
+    presets: Vec<Preset>,
+    load_preset: Option<usize>,
+    crossbreed_preset: Option<usize>,
+    save_preset: bool,
+    /// Whether saving may replace an existing file with the same name.
+    overwrite_preset: bool,
+    /// Presets that failed to load or save, to show in the presets panel.
+    preset_errors: Vec<String>,
+    new_preset_name: String,
+    load_file: bool,
+    load_file_path: String,
+
+    /// Whether new rules are blended in over `morph_duration` seconds rather
+    /// than replacing the current ones immediately.
+    morph: bool,
+    morph_duration: f32,
+    /// Set by the app while a morph is in progress.
+    morph_progress: Option<f32>,
+    skip_morph: bool,
+    /// Set when the rule editor changed the rules this frame.
+    rules_edited: bool,
+
+    showcase: bool,
+    showcase_settings: ShowcaseSettings,
+
+    metrics: MetricsHistory,
+    behaviour: Option<Classification>,
+
+    cluster_parameters: ClusterParameters,
+    color_clusters: bool,
+    /// Whether the clusters panel was open this frame.
+    clusters_visible: bool,
+    clustering: Option<Clustering>,
+
+    rdf_parameters: RdfParameters,
+    /// Whether the pair correlation panel was open this frame.
+    rdf_visible: bool,
+    rdf: Option<RadialDistribution>,
+    export_rdf: bool,
+
+    track_organisms: bool,
+    tracker: OrganismTracker,
+    tracking_step: u64,
+    export_tracks: bool,
+
+    trajectory_parameters: TrajectoryParameters,
+    record_trajectories: bool,
+    /// The current or last recording. Started by the app on the first
+    /// snapshot after recording is switched on.
+    trajectories: Option<TrajectoryRecorder>,
+    export_trajectories_csv: bool,
+    export_trajectories_binary: bool,
+
+    frame_parameters: FrameParameters,
+    record_frames: bool,
+    /// The current recording, started by the app once recording is switched
+    /// on and finished when it is switched off.
+    frames: Option<FrameRecorder>,
+
+    rule_editor: RuleEditor,
+}
+
+impl UIState {
+    fn draw_ui(&mut self, imgui: &mut imgui::Ui, world: &mut World, rules: &mut Rules) {
+        imgui
+            .window("dplife")
+            .position([5.0, 5.0], Always)
+            .collapsed(true, imgui::Condition::Once)
+            .build(|| {
+                self.draw_controls_ui(imgui);
+                self.draw_morph_ui(imgui);
+
+                match self.behaviour {
+                    Some(behaviour) => imgui.text(format!(
+                        "behaviour: {} ({:.0}%)",
+                        behaviour.behaviour.name(),
+                        behaviour.confidence * 100.0
+                    )),
+                    None => imgui.text_disabled("behaviour: measuring"),
+                }
+
+                if imgui.collapsing_header("Presets", TreeNodeFlags::empty()) {
+                    self.draw_presets_ui(imgui);
+                }
+
+                if imgui.collapsing_header("Showcase", TreeNodeFlags::empty()) {
+                    self.draw_showcase_ui(imgui);
+                }
+
+                if imgui.collapsing_header("Metrics", TreeNodeFlags::empty()) {
+                    self.draw_metrics_ui(imgui);
+                }
+
+                self.clusters_visible = imgui.collapsing_header("Clusters", TreeNodeFlags::empty());
+                if self.clusters_visible {
+                    self.draw_clusters_ui(imgui);
+                }
+
+                self.rdf_visible =
+                    imgui.collapsing_header("Pair Correlation", TreeNodeFlags::empty());
+                if self.rdf_visible {
+                    self.draw_rdf_ui(imgui, rules);
+                }
+
+                if imgui.collapsing_header("Trajectories", TreeNodeFlags::empty()) {
+                    self.draw_trajectories_ui(imgui);
+                }
+
+                if imgui.collapsing_header("Frames", TreeNodeFlags::empty()) {
+                    self.draw_frames_ui(imgui);
+                }
+
+                if imgui.collapsing_header("Rules", TreeNodeFlags::empty()) {
+                    self.rules_edited =
+                        self.rule_editor
+                            .draw_ui(imgui, rules, &self.rule_generation_parameters);
+                }
+
+                if imgui.collapsing_header("Rule Generation", TreeNodeFlags::empty()) {
+                    self.draw_rule_generation_ui(imgui);
+                }
+
+                if imgui.collapsing_header("World", TreeNodeFlags::empty()) {
+                    let s = world.settings();
+
+                    Drag::new("friction")
+                        .range(0.0, 1.0)
+                        .speed(0.001)
+                        .build(imgui, &mut s.friction);
+                    Drag::new("force_multiplier")
+                        .range(0.0, 0.1)
+                        .speed(0.0001)
+                        .build(imgui, &mut s.force_multiplier);
+                }
+            });
+    }
+
+    fn draw_presets_ui(&mut self, imgui: &imgui::Ui) {
+        for (index, preset) in self.presets.iter().enumerate() {
+            if imgui.button(format!("{}##preset{index}", preset.name)) {
+                self.load_preset = Some(index);
+            }
+            if imgui.is_item_hovered() && !preset.description.is_empty() {
+                imgui.tooltip_text(&preset.description);
+            }
+            imgui.same_line();
+            if imgui.small_button(format!("crossbreed##preset{index}")) {
+                self.crossbreed_preset = Some(index);
+            }
+        }
+
+        imgui.separator();
+        imgui
+            .input_text("##new_preset_name", &mut self.new_preset_name)
+            .hint("name")
+            .build();
+        imgui.same_line();
+        let exists = !self.new_preset_name.is_empty()
+            && Preset::path_in(Path::new(presets::USER_PRESETS_DIR), &self.new_preset_name)
+                .exists();
+        if imgui.button(if exists { "Overwrite" } else { "Save" })
+            && !self.new_preset_name.is_empty()
+        {
+            self.save_preset = true;
+            self.overwrite_preset = exists;
+        }
+        if exists && imgui.is_item_hovered() {
+            imgui.tooltip_text("A preset with this name has already been saved");
+        }
+        imgui.same_line();
+        if imgui.button("Refresh") {
+            self.refresh_presets();
+        }
+
+        imgui
+            .input_text("##load_file_path", &mut self.load_file_path)
+            .hint("path to .toml")
+            .build();
+        imgui.same_line();
+        if imgui.button("Load File") && !self.load_file_path.is_empty() {
+            self.load_file = true;
+        }
+
+        for error in &self.preset_errors {
+            imgui.text_colored([1.0, 0.3, 0.3, 1.0], error);
+        }
+    }
+
+    /// Loads the presets again, keeping the errors for any that fail.
+    fn refresh_presets(&mut self) {
+        let (presets, errors) = presets::all();
+        self.presets = presets;
+        self.preset_errors = errors.iter().map(|e| format!("{e:#}")).collect();
+    }
+
+    fn draw_controls_ui(&mut self, imgui: &imgui::Ui) {
+        self.reset_particles = imgui.button("Reset Particles");
+        self.new_rules = imgui.button("New Rules");
+        imgui.same_line();
+        self.mutate_rules = imgui.button("Mutate");
+        imgui.same_line();
+        imgui.checkbox("paused", &mut self.paused);
+        if self.paused {
+            imgui.same_line();
+            self.step_once = imgui.small_button("step");
+        }
+        imgui.same_line();
+        if imgui.button("Screenshot") {
+            self.screenshot = true;
+        }
+        if imgui.is_item_hovered() {
+            imgui.tooltip_text("F12. Drop a screenshot onto the window to restore it.");
+        }
+
+        let mut format = SnapshotFormat::ALL
+            .iter()
+            .position(|f| *f == self.snapshot_format)
+            .unwrap();
+        imgui.set_next_item_width(60.0);
+        if imgui.combo(
+            "##snapshot_format",
+            &mut format,
+            &SnapshotFormat::ALL,
+            |f| f.name().into(),
+        ) {
+            self.snapshot_format = SnapshotFormat::ALL[format];
+        }
+        imgui.same_line();
+        self.export_snapshot = imgui.button("Export Particles");
+    }
+
+    fn draw_rule_generation_ui(&mut self, imgui: &imgui::Ui) {
+        let params = &mut self.rule_generation_parameters;
+        distribution_ui(imgui, "min", &mut params.min_distance, 0.0..100.0);
+        distribution_ui(imgui, "max", &mut params.max_distance, 0.0..100.0);
+        distribution_ui(imgui, "force", &mut params.force, 0.0..2.0);
+        imgui.slider(
+            "attraction probability",
+            0.0,
+            1.0,
+            &mut params.attraction_probability,
+        );
+
+        let mut symmetry = Symmetry::ALL
+            .iter()
+            .position(|s| *s == params.symmetry)
+            .unwrap();
+        if imgui.combo("symmetry", &mut symmetry, &Symmetry::ALL, |s| {
+            s.name().into()
+        }) {
+            params.symmetry = Symmetry::ALL[symmetry];
+        }
+
+        imgui.slider("sparsity", 0.0, 1.0, &mut params.sparsity);
+        imgui.slider("self attraction", 0.0, 1.0, &mut params.self_attraction);
+        imgui.checkbox("predator/prey cycle", &mut params.predator_prey_cycle);
+
+        imgui.separator();
+        let mutation = &mut self.mutation_parameters;
+        imgui.slider("mutation amount", 0.0, 1.0, &mut mutation.amount);
+        imgui.slider(
+            "mutation randomize fraction",
+            0.0,
+            1.0,
+            &mut mutation.randomize_fraction,
+        );
+    }
+
+    fn draw_showcase_ui(&mut self, imgui: &imgui::Ui) {
+        let settings = &mut self.showcase_settings;
+        imgui.slider("seconds per rule set", 5.0, 300.0, &mut settings.interval);
+        imgui.checkbox("use presets", &mut settings.use_presets);
+        imgui.checkbox("reset particles", &mut settings.reset_particles);
+        imgui.checkbox("skip collapsed rule sets", &mut settings.skip_collapsed);
+        imgui.checkbox("hide UI", &mut settings.hide_ui);
+
+        let label = if self.showcase { "Stop" } else { "Start" };
+        if imgui.button(label) {
+            self.showcase = !self.showcase;
+        }
+        imgui.same_line();
+        imgui.text_disabled("(Escape stops the showcase)");
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn draw_metrics_ui(&self, imgui: &imgui::Ui) {
+        let Some(latest) = self.metrics.latest() else {
+            imgui.text_disabled("waiting for particles");
+            return;
+        };
+
+        let plot = |label: &str, value: f32, f: fn(&Metrics) -> f32| {
+            imgui
+                .plot_lines(format!("##{label}"), &self.metrics.series(f))
+                .graph_size([METRICS_PLOT_WIDTH, 40.0])
+                .overlay_text(format!("{label}: {value:.3}"))
+                .build();
+        };
+
+        plot("mean speed", latest.mean_speed, |m| m.mean_speed);
+        plot("max speed", latest.max_speed, |m| m.max_speed);
+        plot("kinetic energy", latest.kinetic_energy, |m| {
+            m.kinetic_energy
+        });
+        plot("mean neighbours", latest.mean_neighbours, |m| {
+            m.mean_neighbours
+        });
+        plot("stationary fraction", latest.stationary_fraction, |m| {
+            m.stationary_fraction
+        });
+        plot("dispersion", latest.dispersion, |m| m.dispersion);
+
+        let mean_spread =
+            latest.species_spread.iter().sum::<f32>() / latest.species_spread.len() as f32;
+        plot("mean species spread", mean_spread, |m| {
+            m.species_spread.iter().sum::<f32>() / m.species_spread.len() as f32
+        });
+        imgui
+            .plot_histogram("##species_spread", &latest.species_spread)
+            .scale_min(0.0)
+            .graph_size([METRICS_PLOT_WIDTH, 40.0])
+            .overlay_text("spread by species")
+            .build();
+    }
+
+    fn draw_clusters_ui(&mut self, imgui: &imgui::Ui) {
+        const LISTED_CLUSTERS: usize = 10;
+
+        let params = &mut self.cluster_parameters;
+        imgui.slider("radius", 1.0, 100.0, &mut params.radius);
+        imgui.slider("min points", 1, 50, &mut params.min_points);
+        imgui.checkbox("colour by cluster", &mut self.color_clusters);
+
+        imgui.checkbox("track organisms", &mut self.track_organisms);
+
+        let Some(clustering) = &self.clustering else {
+            imgui.text_disabled("waiting for particles");
+            return;
+        };
+
+        imgui.text(format!(
+            "{} clusters, {} particles unclustered",
+            clustering.clusters.len(),
+            clustering.unclustered()
+        ));
+
+        for (index, cluster) in clustering.clusters.iter().take(LISTED_CLUSTERS).enumerate() {
+            imgui.text(format!("{:2}: {:6}", index + 1, cluster.size()));
+
+            for kind in ParticleKind::all() {
+                let count = cluster.composition[kind.index()];
+                if count > 0 {
+                    imgui.same_line();
+                    imgui.text_colored(kind.as_rgba(), count.to_string());
+                }
+            }
+        }
+
+        if self.track_organisms {
+            imgui.separator();
+            self.draw_tracking_ui(imgui);
+        }
+    }
+
+    /// Plots g(r) for the pair of kinds selected in the rule editor, with
+    /// markers at that rule's min and max distance.
+    fn draw_rdf_ui(&mut self, imgui: &imgui::Ui, rules: &Rules) {
+        let params = &mut self.rdf_parameters;
+        imgui.slider("max distance", 10.0, 500.0, &mut params.max_distance);
+        imgui.slider("bins", 10, 200, &mut params.bins);
+
+        let Some(rdf) = &self.rdf else {
+            imgui.text_disabled("waiting for particles");
+            return;
+        };
+
+        let (a, b) = self.rule_editor.selected();
+        imgui.text(format!(
+            "g(r) of {} around {} (pick the pair in Rules)",
+            b.index(),
+            a.index()
+        ));
+
+        let values = rdf.get(a, b);
+        let peak = values.iter().fold(1.0_f32, |peak, g| peak.max(*g));
+        imgui
+            .plot_lines("##rdf", values)
+            .scale_min(0.0)
+            .scale_max(peak)
+            .graph_size([METRICS_PLOT_WIDTH, 80.0])
+            .overlay_text(format!("0 - {:.0}, peak {peak:.2}", rdf.max_distance))
+            .build();
+
+        let [min_x, min_y] = imgui.item_rect_min();
+        let [max_x, max_y] = imgui.item_rect_max();
+        let rule = rules.get_rule(a, b);
+        let draw_list = imgui.get_window_draw_list();
+        for (distance, color) in [
+            (rule.min_distance, [1.0, 0.3, 0.3, 1.0]),
+            (rule.max_distance, [0.3, 1.0, 0.3, 1.0]),
+        ] {
+            if distance <= rdf.max_distance {
+                let x = min_x + (max_x - min_x) * distance / rdf.max_distance;
+                draw_list.add_line([x, min_y], [x, max_y], color).build();
+            }
+        }
+        imgui.text_colored([1.0, 0.3, 0.3, 1.0], "min_distance");
+        imgui.same_line();
+        imgui.text_colored([0.3, 1.0, 0.3, 1.0], "max_distance");
+
+        self.export_rdf = imgui.button("Export CSV##rdf");
+    }
+
+    fn draw_tracking_ui(&mut self, imgui: &imgui::Ui) {
+        const LISTED_ORGANISMS: usize = 10;
+
+        imgui.slider(
+            "min organism size",
+            1,
+            200,
+            &mut self.tracker.params.min_size,
+        );
+
+        let summary = self.tracker.summary(self.tracking_step);
+        imgui.text(format!(
+            "{} organisms alive, mean age {:.0} steps",
+            summary.alive, summary.mean_age
+        ));
+        imgui.text(format!(
+            "{} ended, mean lifetime {:.0} steps",
+            summary.ended, summary.mean_lifetime
+        ));
+
+        let mut alive: Vec<_> = self.tracker.alive().collect();
+        alive.sort_by_key(|track| track.first_step());
+        for track in alive.iter().take(LISTED_ORGANISMS) {
+            let last = track.last();
+            imgui.text(format!(
+                "#{}: age {}, size {}, speed {:.2}, spin {:.4}",
+                track.id,
+                self.tracking_step.saturating_sub(track.first_step()),
+                last.size,
+                last.velocity.magnitude(),
+                last.angular_velocity
+            ));
+        }
+
+        self.export_tracks = imgui.button("Export Tracks");
+    }
+
+    fn draw_trajectories_ui(&mut self, imgui: &imgui::Ui) {
+        let params = &mut self.trajectory_parameters;
+        imgui.slider("interval (steps)", 1, 100, &mut params.interval);
+
+        let mut all_particles = params.max_particles.is_none();
+        if imgui.checkbox("all particles", &mut all_particles) {
+            params.max_particles = if all_particles {
+                None
+            } else {
+                TrajectoryParameters::default().max_particles
+            };
+        }
+        if let Some(max_particles) = &mut params.max_particles {
+            imgui.slider("max particles", 1, 10000, max_particles);
+        }
+
+        if imgui.checkbox("record", &mut self.record_trajectories) && self.record_trajectories {
+            self.trajectories = None;
+        }
+
+        let Some(trajectories) = &self.trajectories else {
+            return;
+        };
+        imgui.text(format!(
+            "{} frames of {} particles",
+            trajectories.num_frames(),
+            trajectories.num_particles()
+        ));
+        if trajectories.is_interrupted() {
+            imgui.text_disabled("stopped: the particles were reset");
+        }
+
+        self.export_trajectories_csv = imgui.button("Export CSV");
+        imgui.same_line();
+        self.export_trajectories_binary = imgui.button("Export Binary");
+    }
+
+    fn draw_frames_ui(&mut self, imgui: &imgui::Ui) {
+        let params = &mut self.frame_parameters;
+
+        let mut format = FrameFormat::ALL
+            .iter()
+            .position(|f| *f == params.format)
+            .unwrap();
+        if imgui.combo("format", &mut format, &FrameFormat::ALL, |f| {
+            f.name().into()
+        }) {
+            params.format = FrameFormat::ALL[format];
+        }
+        imgui.slider("interval (steps)", 1, 100, &mut params.interval);
+        imgui.slider("width", 16, 4096, &mut params.size[0]);
+        imgui.slider("height", 16, 4096, &mut params.size[1]);
+        if params.format != FrameFormat::Png {
+            imgui.slider("frame rate", 1, 120, &mut params.fps);
+        }
+        if params.format == FrameFormat::Gif {
+            imgui.slider(
+                "palette size",
+                2,
+                frames::MAX_PALETTE_SIZE,
+                &mut params.palette_size,
+            );
+        }
+
+        let mut limited = params.duration.is_some();
+        if imgui.checkbox("stop after", &mut limited) {
+            params.duration = limited.then_some(300);
+        }
+        if let Some(duration) = &mut params.duration {
+            imgui.same_line();
+            imgui.slider("steps", 1, 10000, duration);
+        }
+
+        imgui.checkbox("record", &mut self.record_frames);
+
+        if let Some(frames) = &self.frames {
+            imgui.text(format!(
+                "{} frames to {}",
+                frames.num_frames(),
+                frames.path().display()
+            ));
+        }
+    }
+
+    fn draw_morph_ui(&mut self, imgui: &imgui::Ui) {
+        imgui.checkbox("morph", &mut self.morph);
+        if self.morph {
+            imgui.same_line();
+            imgui.set_next_item_width(100.0);
+            Drag::new("seconds")
+                .range(0.1, 60.0)
+                .speed(0.05)
+                .build(imgui, &mut self.morph_duration);
+        }
+        if let Some(progress) = self.morph_progress {
+            imgui::ProgressBar::new(progress)
+                .size([150.0, 0.0])
+                .overlay_text("morphing")
+                .build(imgui);
+            imgui.same_line();
+            self.skip_morph = imgui.small_button("skip");
+        }
+    }
+}
+
+/// Draws the controls for one of the rule generation distributions. `limits`
+/// is the range of values that the drag widgets allow.
+fn distribution_ui(
+    imgui: &imgui::Ui,
+    label: &str,
+    distribution: &mut Distribution,
+    limits: Range<f32>,
+) {
+    let _id = imgui.push_id(label);
+    let speed = (limits.end - limits.start) / 200.0;
+
+    let mut kind = DistributionKind::ALL
+        .iter()
+        .position(|k| *k == distribution.kind())
+        .unwrap();
+    if imgui.combo(label, &mut kind, &DistributionKind::ALL, |k| {
+        k.name().into()
+    }) {
+        *distribution = distribution.to_kind(DistributionKind::ALL[kind]);
+    }
+
+    match distribution {
+        Distribution::Uniform(range) | Distribution::LogUniform(range) => {
+            DragRange::new("range")
+                .range(limits.start, limits.end)
+                .speed(speed)
+                .build(imgui, &mut range.start, &mut range.end);
+
+            if (range.start - range.end).abs() < 0.001 {
+                range.end = range.start + 0.001;
+            }
+        }
+        Distribution::Normal { mean, std_dev } => {
+            Drag::new("mean")
+                .range(limits.start, limits.end)
+                .speed(speed)
+                .build(imgui, mean);
+            Drag::new("std dev")
+                .range(0.0, limits.end - limits.start)
+                .speed(speed)
+                .build(imgui, std_dev);
+        }
+        Distribution::Discrete(values) => {
+            let mut remove = None;
+            for (index, value) in values.iter_mut().enumerate() {
+                let _id = imgui.push_id_usize(index);
+                Drag::new("##value")
+                    .range(limits.start, limits.end)
+                    .speed(speed)
+                    .build(imgui, value);
+                imgui.same_line();
+                if imgui.small_button("x") {
+                    remove = Some(index);
+                }
+            }
+
+            if let Some(index) = remove {
+                values.remove(index);
+            }
+
+            if imgui.small_button("add value") {
+                values.push(values.last().copied().unwrap_or(limits.start));
+            }
+        }
+    }
+}
+
+struct App {
+    renderer: Renderer,
+    points_renderer: PointsRenderer,
+    camera: Camera,
+
+    rendered_ui: RenderedUI,
+    ui_state: UIState,
+
+    world: World,
+    world_rules: Rules,
+    morph: Option<RuleMorph>,
+    showcase: Option<Showcase>,
+    last_clustering: Instant,
+    last_rdf: Instant,
+    /// The step the last recorded frame was taken at, so that a frame isn't
+    /// recorded twice while paused.
+    last_frame_step: Option<u64>,
+
+    mouse: Mouse,
+}
+
+impl Drop for App {
+    fn drop(&mut self) {
+        self.renderer.shutdown();
+    }
+}
+
+impl App {
+    fn new(
+        renderer: Renderer,
+        imgui_manager: Arc<Mutex<ImguiManager>>,
+        config: &ViewerConfig,
+    ) -> Self {
+        let mut im = imgui_manager.lock().unwrap();
+
+        let imgui_renderer = im.new_renderer(
+            &renderer.device,
+            &renderer.descriptor_heap.get_descriptor_handles(0),
+        );
+
+        drop(im);
+
+        let rendered_ui = RenderedUI {
+            imgui_manager,
+            imgui_renderer,
+        };
+        let mut ui_state = UIState {
+            rule_generation_parameters: RuleGenerationParameters {
+                species: config.species,
+                ..Default::default()
+            },
+            morph_duration: 5.0,
+            paused: config.paused,
+            ..Default::default()
+        };
+        ui_state.refresh_presets();
+
+        let camera = Camera::new(*renderer.get_viewport());
+        let points_renderer = renderer.new_points_renderer();
+
+        let world_size = config.world_size.unwrap_or_else(|| {
+            Vec2::new(
+                renderer.get_viewport().Width * 3.0,
+                renderer.get_viewport().Height * 3.0,
+            )
+        });
+
+        let mut world = World::new(
+            &renderer.device,
+            config.particles,
+            world_size,
+            config.species,
+            config.seed,
+        );
+        if let Some(import) = &config.import {
+            world.import(import.fit(world_size));
+        }
+        let world_rules = if let Some(preset) = &config.preset {
+            preset.apply_settings(world.settings());
+            preset.rules
+        } else {
+            Rules::new_random_with_rng(
+                &ui_state.rule_generation_parameters,
+                &mut StdRng::seed_from_u64(config.seed),
+            )
+        };
+
+        App {
+            renderer,
+            points_renderer,
+            camera,
+            rendered_ui,
+            ui_state,
+            world,
+            world_rules,
+            morph: None,
+            showcase: None,
+            last_clustering: Instant::now(),
+            last_rdf: Instant::now(),
+            last_frame_step: None,
+            mouse: Mouse::new(),
+        }
+    }
+
+    fn start_tick(&mut self) {
+        self.mouse.start_tick();
+    }
+
+    /// Switches to `rules`, either immediately or by morphing from the current
+    /// rules if that is enabled.
+    /// Changes the rules, or starts morphing to them. The metrics gathered
+    /// under the old rules are dropped so they don't skew the classification.
+    fn set_rules(&mut self, rules: &Rules) {
+        self.ui_state.metrics.clear();
+        if self.ui_state.morph {
+            self.morph = Some(RuleMorph::new(
+                &self.world_rules,
+                rules,
+                Duration::from_secs_f32(self.ui_state.morph_duration.max(0.0)),
+            ));
+        } else {
+            self.morph = None;
+            self.world_rules = *rules;
+        }
+    }
+
+    /// Applies a preset's settings and rules. When morphing the particles are
+    /// kept so that the existing structures can be seen changing shape.
+    fn apply_preset(&mut self, preset: &Preset) {
+        preset.apply_settings(self.world.settings());
+        self.set_rules(&preset.rules);
+        if !self.ui_state.morph {
+            self.reset_particles();
+        }
+    }
+
+    /// Sends the particles back to where they started. Any organisms being
+    /// tracked are ended and the metrics history is dropped, since the
+    /// particles they describe are gone.
+    fn reset_particles(&mut self) {
+        self.world.reset_particles();
+        self.ui_state.tracker.end_all();
+        self.ui_state.metrics.clear();
+    }
+
+    /// Draws what the camera sees on the CPU, from the particles that were last
+    /// read back. `None` if none have been read back yet.
+    fn render_view(&self, size: [u32; 2]) -> Option<Image> {
+        let particles = self.world.particles();
+        if particles.is_empty() {
+            return None;
+        }
+
+        let mut vertices: Vec<_> = particles.iter().map(Particle::vertex).collect();
+        if let Some(colors) = self.world.color_override() {
+            for (vertex, color) in vertices.iter_mut().zip(colors) {
+                vertex.color = *color;
+            }
+        }
+
+        Some(raster::rasterise(
+            &vertices,
+            self.camera.get_matrix_for_size(size),
+            size[0],
+            size[1],
+        ))
+    }
+
+    /// Saves what the camera sees, along with what's needed to start the scene
+    /// again.
+    fn save_screenshot(&mut self) {
+        let viewport = self.renderer.get_viewport();
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let size = [viewport.Width as u32, viewport.Height as u32];
+        let Some(image) = self.render_view(size) else {
+            eprintln!("Failed to save screenshot: no particles have been read back yet");
+            return;
+        };
+
+        let scene = Scene {
+            seed: self.world.seed(),
+            particles: self.world.num_particles(),
+            species: self.world.species(),
+            world_size: Vec2::from(self.world.settings().world_size),
+            preset: Preset::new("Screenshot", self.world.settings(), &self.world_rules),
+        };
+
+        let path = screenshot::next_path();
+        match screenshot::save(&path, &image, Some(&scene)) {
+            Ok(()) => println!("Wrote {}", path.display()),
+            Err(e) => eprintln!("Failed to save screenshot: {e:#}"),
+        }
+    }
+
+    /// Starts a scene from a screenshot again. The number of particles can
+    /// only be set when the viewer starts, so it is kept as it is.
+    fn restore_scene(&mut self, scene: &Scene) {
+        if scene.particles != self.world.num_particles() {
+            eprintln!(
+                "The scene has {} particles, but the viewer has {}. Pass the screenshot to --scene to restore them all.",
+                scene.particles,
+                self.world.num_particles()
+            );
+        }
+
+        self.world
+            .restart(scene.world_size, scene.species, scene.seed);
+        self.ui_state.tracker.end_all();
+        self.ui_state.rule_generation_parameters.species = scene.species;
+        scene.preset.apply_settings(self.world.settings());
+        self.morph = None;
+        self.world_rules = scene.preset.rules;
+    }
+
+    /// Starts again from imported particles. As with scenes, the number of
+    /// particles is fixed once the viewer starts, so it must match.
+    fn import_particles(&mut self, import: &Import) {
+        if import.num_particles() != self.world.num_particles() {
+            eprintln!(
+                "Failed to import particles: the file has {}, but the viewer has {}. Pass the file to --import to start with them instead.",
+                import.num_particles(),
+                self.world.num_particles()
+            );
+            return;
+        }
+
+        let world_size = Vec2::from(self.world.settings().world_size);
+        self.world.import(import.fit(world_size));
+        self.ui_state.tracker.end_all();
+    }
+
+    /// Finds clusters every so often, when the clusters panel is open or the
+    /// particles are coloured by cluster.
+    fn update_clusters(&mut self) {
+        const CLUSTERING_INTERVAL: Duration = Duration::from_millis(250);
+
+        let ui_state = &mut self.ui_state;
+        if !ui_state.color_clusters {
+            self.world.set_color_override(None);
+        }
+
+        if !(ui_state.color_clusters || ui_state.clusters_visible || ui_state.track_organisms)
+            || self.last_clustering.elapsed() < CLUSTERING_INTERVAL
+            || self.world.particles().is_empty()
+        {
+            return;
+        }
+        self.last_clustering = Instant::now();
+
+        let world_size = Vec2::from(self.world.settings().world_size);
+        let clustering = clusters::find_clusters(
+            self.world.particles(),
+            world_size,
+            &ui_state.cluster_parameters,
+        );
+
+        if ui_state.color_clusters {
+            self.world.set_color_override(Some(clustering.colors()));
+        }
+        // While paused the same particles are read back again, so only track
+        // them once the step has moved on.
+        if ui_state.track_organisms && self.world.particles_step() != ui_state.tracking_step {
+            ui_state.tracking_step = self.world.particles_step();
+            ui_state.tracker.update(
+                ui_state.tracking_step,
+                self.world.particles(),
+                &clustering,
+                world_size,
+            );
+        }
+        ui_state.clustering = Some(clustering);
+    }
+
+    fn update_rdf(&mut self) {
+        const RDF_INTERVAL: Duration = Duration::from_secs(1);
+
+        if !self.ui_state.rdf_visible
+            || self.last_rdf.elapsed() < RDF_INTERVAL
+            || self.world.particles().is_empty()
+        {
+            return;
+        }
+        self.last_rdf = Instant::now();
+
+        let world_size = Vec2::from(self.world.settings().world_size);
+        self.ui_state.rdf = Some(RadialDistribution::measure(
+            self.world.particles(),
+            world_size,
+            &self.ui_state.rdf_parameters,
+        ));
+    }
+
+    fn update_trajectories(&mut self) {
+        let world_size = Vec2::from(self.world.settings().world_size);
+        let ui_state = &mut self.ui_state;
+        let particles = self.world.particles();
+        if !ui_state.record_trajectories || particles.is_empty() {
+            return;
+        }
+
+        let step = self.world.particles_step();
+        if let Some(trajectories) = &mut ui_state.trajectories {
+            trajectories.record(step, particles);
+        } else {
+            ui_state.trajectories = Some(TrajectoryRecorder::new(
+                ui_state.trajectory_parameters.clone(),
+                step,
+                particles,
+                world_size,
+            ));
+        }
+    }
+
+    /// Writes out whatever the UI asked to export this frame.
+    fn write_exports(&mut self) {
+        if std::mem::take(&mut self.ui_state.export_snapshot) {
+            let format = self.ui_state.snapshot_format;
+            let path = PathBuf::from(format!("{PARTICLES_FILE}.{}", format.extension()));
+            let world_size = Vec2::from(self.world.settings().world_size);
+            let particles = self.world.particles();
+            if particles.is_empty() {
+                eprintln!("Failed to export particles: none have been read back yet");
+            } else {
+                match fs::write(&path, format.encode(particles, world_size)) {
+                    Ok(()) => println!("Wrote {}", path.display()),
+                    Err(e) => eprintln!("Failed to export particles: {e:#}"),
+                }
+            }
+        }
+
+        if std::mem::take(&mut self.ui_state.export_rdf) {
+            if let Some(rdf) = &self.ui_state.rdf {
+                let path = Path::new(RDF_FILE);
+                match fs::write(path, rdf.to_csv()) {
+                    Ok(()) => println!("Wrote {}", path.display()),
+                    Err(e) => eprintln!("Failed to export pair correlations: {e:#}"),
+                }
+            }
+        }
+
+        if std::mem::take(&mut self.ui_state.export_tracks) {
+            let path = Path::new(TRACKS_FILE);
+            match self.ui_state.tracker.export(path) {
+                Ok(()) => println!("Wrote {}", path.display()),
+                Err(e) => eprintln!("Failed to export tracks: {e:#}"),
+            }
+        }
+
+        if let Some(trajectories) = &self.ui_state.trajectories {
+            if std::mem::take(&mut self.ui_state.export_trajectories_csv) {
+                let path = Path::new(TRAJECTORIES_CSV_FILE);
+                match trajectories.export_csv(path) {
+                    Ok(()) => println!("Wrote {}", path.display()),
+                    Err(e) => eprintln!("Failed to export trajectories: {e:#}"),
+                }
+            }
+            if std::mem::take(&mut self.ui_state.export_trajectories_binary) {
+                let path = Path::new(TRAJECTORIES_BINARY_FILE);
+                match trajectories.export_binary(path) {
+                    Ok(()) => println!("Wrote {}", path.display()),
+                    Err(e) => eprintln!("Failed to export trajectories: {e:#}"),
+                }
+            }
+        }
+    }
+
+    /// Records a frame for each step that is due while recording is on. Frames
+    /// follow the simulation steps rather than the window's frame rate.
+    fn update_frames(&mut self) {
+        if !self.ui_state.record_frames {
+            if let Some(frames) = self.ui_state.frames.take() {
+                let path = frames.path().to_owned();
+                match frames.finish() {
+                    Ok(()) => println!("Wrote {}", path.display()),
+                    Err(e) => eprintln!("Failed to record frames: {e:#}"),
+                }
+            }
+            return;
+        }
+
+        if self.ui_state.frames.is_none() {
+            let params = self.ui_state.frame_parameters.clone();
+            let path = match params.format {
+                FrameFormat::Png => Path::new(FRAMES_DIR),
+                FrameFormat::Y4m => Path::new(FRAMES_Y4M_FILE),
+                FrameFormat::Gif => Path::new(FRAMES_GIF_FILE),
+            };
+            match FrameRecorder::new(params, path) {
+                Ok(frames) => self.ui_state.frames = Some(frames),
+                Err(e) => {
+                    eprintln!("Failed to record frames: {e:#}");
+                    self.ui_state.record_frames = false;
+                    return;
+                }
+            }
+            self.last_frame_step = None;
+        }
+
+        let step = self.world.particles_step();
+        let Some(frames) = &self.ui_state.frames else {
+            return;
+        };
+        if frames.is_complete(step) {
+            self.ui_state.record_frames = false;
+            return;
+        }
+        if self.last_frame_step == Some(step) || !frames.is_due(step) {
+            return;
+        }
+        let Some(image) = self.render_view(frames.size()) else {
+            return;
+        };
+
+        self.last_frame_step = Some(step);
+        if let Some(frames) = &mut self.ui_state.frames {
+            if let Err(e) = frames.write(step, &image) {
+                eprintln!("Failed to record frames: {e:#}");
+                self.ui_state.record_frames = false;
+            }
+        }
+    }
+
+    fn update_showcase(&mut self) {
+        match (self.ui_state.showcase, self.showcase.is_some()) {
+            (true, false) => self.showcase = Some(Showcase::new()),
+            (false, true) => self.showcase = None,
+            _ => (),
+        }
+
+        let settle_time = if self.ui_state.showcase_settings.reset_particles {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f32(self.ui_state.morph_duration.max(0.0))
+        };
+        let change = self.showcase.as_mut().and_then(|showcase| {
+            showcase.update(
+                &self.ui_state.showcase_settings,
+                self.ui_state.behaviour,
+                settle_time,
+                self.ui_state.paused,
+            )
+        });
+
+        match change {
+            Some(ShowcaseChange::Next) => self.next_showcase_rules(false),
+            Some(ShowcaseChange::Skip(behaviour)) => {
+                println!("Showcase: skipping {} rules", behaviour.name());
+                self.next_showcase_rules(true);
+            }
+            None => (),
+        }
+    }
+
+    /// Moves the showcase on to a new rule set. Collapsed worlds are replaced
+    /// outright, since there's nothing left in them worth morphing.
+    fn next_showcase_rules(&mut self, collapsed: bool) {
+        let settings = &self.ui_state.showcase_settings;
+
+        let preset = if settings.use_presets {
+            self.ui_state.presets.choose(&mut rand::rng())
+        } else {
+            None
+        };
+        let rules = match preset {
+            Some(preset) => {
+                preset.apply_settings(self.world.settings());
+                preset.rules
+            }
+            None => Rules::new_random(&self.ui_state.rule_generation_parameters),
+        };
+
+        if collapsed || settings.reset_particles {
+            self.morph = None;
+            self.world_rules = rules;
+            self.reset_particles();
+        } else {
+            self.morph = Some(RuleMorph::new(
+                &self.world_rules,
+                &rules,
+                Duration::from_secs_f32(self.ui_state.morph_duration.max(0.0)),
+            ));
+        }
+
+        self.ui_state.metrics.clear();
+        if let Some(showcase) = &mut self.showcase {
+            showcase.changed();
+        }
+    }
+
+    fn update(&mut self) {
+        if self.ui_state.new_rules {
+            self.set_rules(&Rules::new_random(
+                &self.ui_state.rule_generation_parameters,
+            ));
+        }
+
+        if self.ui_state.mutate_rules {
+            self.set_rules(&self.world_rules.mutate(
+                &self.ui_state.mutation_parameters,
+                &self.ui_state.rule_generation_parameters,
+                &mut rand::rng(),
+            ));
+        }
+
+        if let Some(index) = self.ui_state.load_preset.take() {
+            let preset = self.ui_state.presets[index].clone();
+            self.apply_preset(&preset);
+        }
+
+        if std::mem::take(&mut self.ui_state.load_file) {
+            match Preset::load(Path::new(&self.ui_state.load_file_path)) {
+                Ok(preset) => self.apply_preset(&preset),
+                Err(e) => eprintln!("Failed to load rules: {e:#}"),
+            }
+        }
+
+        if let Some(path) = self.ui_state.load_scene.take() {
+            match screenshot::load_scene(&path) {
+                Ok(scene) => self.restore_scene(&scene),
+                Err(e) => eprintln!("Failed to load scene: {e:#}"),
+            }
+        }
+
+        if let Some(path) = self.ui_state.import_particles.take() {
+            match Import::load(&path, self.world.species()) {
+                Ok(import) => self.import_particles(&import),
+                Err(e) => eprintln!("Failed to import particles: {e:#}"),
+            }
+        }
+
+        if let Some(index) = self.ui_state.crossbreed_preset.take() {
+            self.set_rules(
+                &self
+                    .world_rules
+                    .crossover(&self.ui_state.presets[index].rules, &mut rand::rng()),
+            );
+        }
+
+        // Only record metrics while running, so that pausing doesn't fill the
+        // history with copies of the same sample.
+        if let Some(metrics) = self.world.metrics().filter(|_| !self.ui_state.paused) {
+            self.ui_state.metrics.push(*metrics);
+        }
+        self.ui_state.behaviour = classify(&self.ui_state.metrics.recent(CLASSIFICATION_WINDOW));
+
+        self.update_showcase();
+        self.update_clusters();
+        self.update_rdf();
+        self.update_trajectories();
+        self.update_frames();
+
+        self.write_exports();
+
+        if std::mem::take(&mut self.ui_state.skip_morph) {
+            if let Some(morph) = self.morph.take() {
+                self.world_rules = *morph.target();
+            }
+        }
+
+        let edited = std::mem::take(&mut self.ui_state.rules_edited);
+        if let Some(morph) = &self.morph {
+            if morph.step(&mut self.world_rules, edited) {
+                self.ui_state.morph_progress = Some(morph.progress());
+            } else {
+                self.morph = None;
+            }
+        }
+        if self.morph.is_none() {
+            self.ui_state.morph_progress = None;
+        }
+
+        if std::mem::take(&mut self.ui_state.save_preset) {
+            let mut preset = Preset::new(
+                &self.ui_state.new_preset_name,
+                self.world.settings(),
+                &self.world_rules,
+            );
+            let dir = Path::new(presets::USER_PRESETS_DIR);
+            match preset.save(dir, std::mem::take(&mut self.ui_state.overwrite_preset)) {
+                Ok(path) => {
+                    preset.path = Some(path);
+                    let presets = &mut self.ui_state.presets;
+                    match presets.iter_mut().find(|p| p.path == preset.path) {
+                        Some(existing) => *existing = preset,
+                        None => presets.push(preset),
+                    }
+                }
+                Err(e) => self
+                    .ui_state
+                    .preset_errors
+                    .push(format!("Failed to save preset: {e:#}")),
+            }
+        }
+
+        if self.ui_state.reset_particles {
+            self.reset_particles();
+        }
+
+        if std::mem::take(&mut self.ui_state.screenshot) {
+            self.save_screenshot();
+        }
+
+        self.camera.update(&self.mouse);
+    }
+
+    fn render(&mut self) {
+        self.renderer.start_new_frame();
+
+        let cl = self.renderer.new_command_list();
+        if !self.ui_state.paused || std::mem::take(&mut self.ui_state.step_once) {
+            self.world.update(&self.world_rules, &cl);
+        }
+        unsafe {
+            cl.Close().unwrap();
+        }
+
+        self.renderer.execute_command_lists(ecl![cl]);
+
+        let render_target = self.renderer.get_render_target().clone();
+
+        let cl = self.renderer.new_command_list();
+
+        unsafe {
+            cl.ResourceBarrier(&[transition_barrier(
+                &render_target,
+                D3D12_RESOURCE_STATE_PRESENT,
+                D3D12_RESOURCE_STATE_RENDER_TARGET,
+            )]);
+        }
+
+        self.renderer.set_viewports_and_scissors(&cl);
+
+        unsafe {
+            let rtv = self.renderer.get_rtv_handle();
+
+            cl.OMSetRenderTargets(1, Some(&rtv), false, None);
+            cl.ClearRenderTargetView(rtv, &[0.0_f32, 0.0_f32, 0.0_f32, 1.0_f32], None);
+        }
+
+        let (vertex_buffer, num_points) = self.world.get_vertex_buffer();
+        self.points_renderer
+            .render(&self.camera, &cl, vertex_buffer, num_points);
+
+        // Prepare UI
+        {
+            let mut imgui_manager = self.rendered_ui.imgui_manager.lock().unwrap();
+
+            let imgui = imgui_manager.new_frame(&mut self.rendered_ui.imgui_renderer);
+
+            let hide_ui = self.showcase.is_some() && self.ui_state.showcase_settings.hide_ui;
+            if !hide_ui {
+                self.ui_state
+                    .draw_ui(imgui, &mut self.world, &mut self.world_rules);
+            }
+
+            self.mouse.draw_ui(imgui);
+
+            imgui_manager.render(&mut self.rendered_ui.imgui_renderer, &cl);
+        }
+
+        unsafe {
+            cl.ResourceBarrier(&[transition_barrier(
+                &render_target,
+                D3D12_RESOURCE_STATE_RENDER_TARGET,
+                D3D12_RESOURCE_STATE_PRESENT,
+            )]);
+
+            cl.Close().unwrap();
+        }
+
+        self.renderer.execute_command_lists(ecl![cl]);
+        self.renderer.present();
+
+        self.renderer.end_frame();
+    }
+
+    fn handle_event<T>(&mut self, event: Event<T>) {
+        if let Event::WindowEvent {
+            event: window_event,
+            ..
+        } = event
+        {
+            self.mouse.handle_event(&window_event);
+
+            if let WindowEvent::DroppedFile(path) = &window_event {
+                let extension = path
+                    .extension()
+                    .and_then(|extension| extension.to_str())
+                    .map(str::to_ascii_lowercase);
+                if matches!(extension.as_deref(), Some("csv" | "ply")) {
+                    self.ui_state.import_particles = Some(path.clone());
+                } else {
+                    self.ui_state.load_scene = Some(path.clone());
+                }
+            }
+
+            if let WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        logical_key: Key::Named(key),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } = window_event
+            {
+                match key {
+                    NamedKey::Escape => self.ui_state.showcase = false,
+                    NamedKey::Space => self.ui_state.paused = !self.ui_state.paused,
+                    NamedKey::F12 => self.ui_state.screenshot = true,
+                    _ => (),
+                }
+            }
+        }
+    }
+}
+
+pub struct Mouse {
+    pub position: Vec2<f64>,
+    pub left_button: ElementState,
+    pub right_button: ElementState,
+    pub middle_button: ElementState,
+    pub wheel: f32,
+}
+
+impl Mouse {
+    fn new() -> Self {
+        Mouse {
+            position: Vec2::zero(),
+            left_button: ElementState::Released,
+            right_button: ElementState::Released,
+            middle_button: ElementState::Released,
+            wheel: 0.0,
+        }
+    }
+
+    fn start_tick(&mut self) {
+        self.wheel = 0.0;
+    }
+
+    fn handle_event(&mut self, window_event: &WindowEvent) {
+        match window_event {
+            WindowEvent::CursorMoved { position, .. } => {
+                self.position = Vec2::new(position.x, position.y);
+            }
+            WindowEvent::MouseInput { state, button, .. } => {
+                use winit::event::MouseButton::{Left, Middle, Right};
+
+                match button {
+                    Left => self.left_button = *state,
+                    Right => self.right_button = *state,
+                    Middle => self.middle_button = *state,
+                    _ => (),
+                }
+            }
+            WindowEvent::MouseWheel {
+                delta: MouseScrollDelta::LineDelta(_, delta_y),
+                ..
+            } => {
+                self.wheel += delta_y;
+            }
+
+            _ => (),
+        }
+    }
+
+    #[allow(clippy::unused_self)]
+    fn draw_ui(&self, _imgui: &mut imgui::Ui) {
+        // _imgui.text(format!(
+        //     "{:?} {:?} {:?} {:?} {:?}",
+        //     self.position, self.left_button, self.right_button, self.middle_button, self.wheel
+        // ));
+    }
+}
+
+fn main_thread(
+    rx: &Receiver<ThreadMessage>,
+    renderer: Renderer,
+    imgui_manager: Arc<Mutex<ImguiManager>>,
+    config: &ViewerConfig,
+) {
+    let mut app = App::new(renderer, imgui_manager, config);
+
+    'mainloop: loop {
+        app.start_tick();
+
+        #[allow(clippy::never_loop)]
+        for message in rx.try_iter() {
+            match message {
+                ThreadMessage::Quit => break 'mainloop,
+                ThreadMessage::Event(event) => app.handle_event(event),
+            }
+        }
+
+        app.update();
+        app.render();
+    }
+}